@@ -1,17 +1,29 @@
 use anyhow::{Context, Result};
 use log::{debug, info, warn};
-use std::collections::HashMap;
-use std::os::unix::process::ExitStatusExt;
-use std::path::PathBuf;
-use std::time::Instant;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use crate::benchmarks::chain_state::ChainState;
+use crate::benchmarks::debug_log::{read_bench_timings, read_tip_updates, SyncProgress};
 use crate::benchmarks::export::ResultExporter;
 use crate::benchmarks::hook_runner::{HookArgs, HookRunner, HookStage};
-use crate::benchmarks::log_monitor::LogMonitor;
+use crate::benchmarks::host_monitor::device_for_path;
+use crate::benchmarks::log_monitor::LogRule;
 use crate::benchmarks::parameters::{ParameterList, ParameterMatrix, ParameterUtils};
+use crate::benchmarks::peer::ManagedPeer;
 use crate::benchmarks::perf::PerfInstrumentor;
-use crate::benchmarks::profiler::{ProfileResult, Profiler};
-use crate::benchmarks::results::{BenchmarkResult, InstrumentationType, ResultAnalyzer, RunResult};
+use crate::benchmarks::profiler::Profiler;
+use crate::benchmarks::prune::{BlocksMonitor, PruneConfig, PruneResult, BLOCKS_SAMPLE_INTERVAL};
+use crate::benchmarks::results::{
+    BenchmarkResult, InstrumentationType, PhaseTiming, ResultAnalyzer, RunResult, RunStatus,
+};
+use crate::benchmarks::rpc::{RpcClient, RpcCollector};
+use crate::benchmarks::stall::StallConfig;
+use crate::benchmarks::supervisor::{MeasureMarker, ProcessSupervisor, StopReason, SupervisedRun};
+use crate::benchmarks::timeline::{Timeline, TimelineAlignment, DEFAULT_HEIGHT_BUCKET};
+use crate::benchmarks::utils::{get_datadir_sizes, get_network_datadir, DEFAULT_RPC_PORT};
+use crate::cgroup::{is_cgroup_dir, Cgroup, ResourceLimits};
 use crate::command::CommandExecutor;
 
 /// Low-level benchmark executor that handles the actual command execution and measurement
@@ -33,10 +45,45 @@ pub struct BenchmarkRunner {
     profile_interval: u64,
     /// Cores to constrain benchmarks to
     benchmark_cores: Option<String>,
-    /// Optional regex pattern to stop the benchmark when matched
-    stop_on_log_pattern: Option<String>,
+    /// Rules applied to the output of the benchmark, including the stop
+    /// pattern
+    log_rules: Vec<LogRule>,
     /// Whether to enable perf instrumentation
     enable_perf_instrumentation: bool,
+    /// Datadir subdirectories whose size is tracked
+    datadir_dirs: Vec<String>,
+    /// Foreign CPU share (percent) above which a profiled run is flagged
+    interference_threshold: Option<f64>,
+    /// Delegated cgroup v2 subtree to isolate each run in
+    cgroup_root: Option<PathBuf>,
+    /// Resource limits applied to each run's cgroup
+    limits: Option<ResourceLimits>,
+    /// Stall detection settings, if enabled
+    stall_config: Option<StallConfig>,
+    /// Maximum duration of a single run
+    timeout: Option<Duration>,
+    /// How profiles are lined up when combined into a timeline
+    timeline_alignment: TimelineAlignment,
+    /// Width of each timeline bucket, in seconds or blocks
+    timeline_bucket: Option<u64>,
+    /// Heights whose time to reach is recorded for each run
+    milestones: Vec<u64>,
+    /// Whether to record the `-debug=bench` validation timing of each run
+    validation_timing: bool,
+    /// Interval in seconds at which node state is polled over RPC
+    rpc_interval: Option<u64>,
+    /// Whether to record the chain state each run ended on
+    verify_chain_state: bool,
+    /// Height at which the node is stopped over RPC
+    stop_at_height: Option<u64>,
+    /// Where the measured interval of each run starts
+    measure_from: Option<MeasureMarker>,
+    /// Where the measured interval of each run ends
+    measure_to: Option<MeasureMarker>,
+    /// Peer serving blocks to the benchmark, checked before each run
+    peer: Option<ManagedPeer>,
+    /// Prune target each run is checked against, in pruned mode
+    prune: Option<PruneConfig>,
 }
 
 /// Builder for BenchmarkRunner
@@ -48,8 +95,25 @@ pub struct BenchmarkRunnerBuilder {
     out_dir: PathBuf,
     profile_interval: u64,
     benchmark_cores: Option<String>,
-    stop_on_log_pattern: Option<String>,
+    log_rules: Vec<LogRule>,
     enable_perf_instrumentation: bool,
+    datadir_dirs: Vec<String>,
+    interference_threshold: Option<f64>,
+    cgroup_root: Option<PathBuf>,
+    limits: Option<ResourceLimits>,
+    stall_config: Option<StallConfig>,
+    timeout: Option<Duration>,
+    timeline_alignment: TimelineAlignment,
+    timeline_bucket: Option<u64>,
+    milestones: Vec<u64>,
+    validation_timing: bool,
+    rpc_interval: Option<u64>,
+    verify_chain_state: bool,
+    stop_at_height: Option<u64>,
+    measure_from: Option<MeasureMarker>,
+    measure_to: Option<MeasureMarker>,
+    peer: Option<ManagedPeer>,
+    prune: Option<PruneConfig>,
 }
 
 impl BenchmarkRunnerBuilder {
@@ -63,8 +127,25 @@ impl BenchmarkRunnerBuilder {
             out_dir,
             profile_interval: 5, // Default to 5 second interval
             benchmark_cores: None,
-            stop_on_log_pattern: None,
+            log_rules: Vec::new(),
             enable_perf_instrumentation: false,
+            datadir_dirs: Vec::new(),
+            interference_threshold: None,
+            cgroup_root: None,
+            limits: None,
+            stall_config: None,
+            timeout: None,
+            timeline_alignment: TimelineAlignment::default(),
+            timeline_bucket: None,
+            milestones: Vec::new(),
+            validation_timing: false,
+            rpc_interval: None,
+            verify_chain_state: false,
+            stop_at_height: None,
+            measure_from: None,
+            measure_to: None,
+            peer: None,
+            prune: None,
         }
     }
 
@@ -97,7 +178,13 @@ impl BenchmarkRunnerBuilder {
 
     /// Set stop on log pattern (regex)
     pub fn stop_on_log_pattern(mut self, pattern: Option<String>) -> Self {
-        self.stop_on_log_pattern = pattern;
+        self.log_rules.extend(pattern.map(LogRule::stop));
+        self
+    }
+
+    /// Add rules applied to each line of the benchmark's output
+    pub fn log_rules(mut self, rules: Vec<LogRule>) -> Self {
+        self.log_rules.extend(rules);
         self
     }
 
@@ -107,11 +194,121 @@ impl BenchmarkRunnerBuilder {
         self
     }
 
+    /// Set the datadir subdirectories whose size is tracked
+    pub fn datadir_dirs(mut self, dirs: Vec<String>) -> Self {
+        self.datadir_dirs = dirs;
+        self
+    }
+
+    /// Set the foreign CPU share above which a profiled run is flagged as
+    /// interfered with
+    pub fn interference_threshold(mut self, threshold: Option<f64>) -> Self {
+        self.interference_threshold = threshold;
+        self
+    }
+
+    /// Isolate each run in a transient cgroup under a delegated cgroup v2
+    /// subtree
+    pub fn cgroup_root(mut self, root: Option<PathBuf>) -> Self {
+        self.cgroup_root = root;
+        self
+    }
+
+    /// Constrain each run to the given resource limits
+    pub fn limits(mut self, limits: Option<ResourceLimits>) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Terminate runs that stall and record them as such
+    pub fn stall_detection(mut self, config: Option<StallConfig>) -> Self {
+        self.stall_config = config;
+        self
+    }
+
+    /// Stop runs that take longer than `timeout` and record them as timed out
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set how profiles of a commit and parameter set are combined into a
+    /// timeline, and the bucket width in seconds or blocks
+    pub fn timeline(mut self, alignment: TimelineAlignment, bucket: Option<u64>) -> Self {
+        self.timeline_alignment = alignment;
+        self.timeline_bucket = bucket;
+        self
+    }
+
+    /// Record the time taken to reach each of these heights
+    pub fn milestones(mut self, milestones: Vec<u64>) -> Self {
+        self.milestones = milestones;
+        self
+    }
+
+    /// Record the time spent in each validation phase from the `-debug=bench`
+    /// lines in debug.log
+    pub fn validation_timing(mut self, enable: bool) -> Self {
+        self.validation_timing = enable;
+        self
+    }
+
+    /// Poll node state over RPC at this interval in seconds during each run
+    pub fn rpc_metrics(mut self, interval: Option<u64>) -> Self {
+        self.rpc_interval = interval;
+        self
+    }
+
+    /// End each run when the node reaches `height`, stopping it over RPC.
+    /// The time taken to shut down is not measured.
+    pub fn stop_at_height(mut self, height: Option<u64>) -> Self {
+        self.stop_at_height = height;
+        self
+    }
+
+    /// Take the interval between these markers as the duration of each run,
+    /// rather than the time from launch to exit
+    pub fn measure(mut self, from: Option<MeasureMarker>, to: Option<MeasureMarker>) -> Self {
+        self.measure_from = from;
+        self.measure_to = to;
+        self
+    }
+
+    /// Record the chain state each completed run ended on, by relaunching the
+    /// binary on its datadir before the conclude hook clears it
+    pub fn verify_chain_state(mut self, enable: bool) -> Self {
+        self.verify_chain_state = enable;
+        self
+    }
+
+    /// Check this peer is healthy before each run, and stop it when the
+    /// runner is dropped
+    pub fn peer(mut self, peer: Option<ManagedPeer>) -> Self {
+        self.peer = peer;
+        self
+    }
+
+    /// Record the blocks directory size of each run, and check it pruned to
+    /// the configured target
+    pub fn prune(mut self, prune: Option<PruneConfig>) -> Self {
+        self.prune = prune;
+        self
+    }
+
     /// Build the BenchmarkRunner, validating parameters if needed
     pub fn build(self) -> Result<BenchmarkRunner> {
         // Validate configuration
-        if self.enable_profiling && self.enable_perf_instrumentation {
-            anyhow::bail!("Cannot enable both profiling and perf instrumentation simultaneously");
+        if self.limits.is_some() && self.cgroup_root.is_none() {
+            anyhow::bail!("Resource limits require cgroup_root to be configured");
+        }
+
+        if let Some(root) = &self.cgroup_root {
+            if !is_cgroup_dir(root) {
+                anyhow::bail!(
+                    "cgroup_root is not a cgroup v2 directory: {}",
+                    root.display()
+                );
+            }
         }
 
         if self.enable_perf_instrumentation {
@@ -129,8 +326,25 @@ impl BenchmarkRunnerBuilder {
             out_dir: self.out_dir,
             profile_interval: self.profile_interval,
             benchmark_cores: self.benchmark_cores,
-            stop_on_log_pattern: self.stop_on_log_pattern,
+            log_rules: self.log_rules,
             enable_perf_instrumentation: self.enable_perf_instrumentation,
+            datadir_dirs: self.datadir_dirs,
+            interference_threshold: self.interference_threshold,
+            cgroup_root: self.cgroup_root,
+            limits: self.limits,
+            stall_config: self.stall_config,
+            timeout: self.timeout,
+            timeline_alignment: self.timeline_alignment,
+            timeline_bucket: self.timeline_bucket,
+            milestones: self.milestones,
+            validation_timing: self.validation_timing,
+            rpc_interval: self.rpc_interval,
+            verify_chain_state: self.verify_chain_state,
+            stop_at_height: self.stop_at_height,
+            measure_from: self.measure_from,
+            measure_to: self.measure_to,
+            peer: self.peer,
+            prune: self.prune,
         })
     }
 }
@@ -179,7 +393,11 @@ impl BenchmarkRunner {
         );
 
         // Run the setup script once before all benchmark runs
-        self.hook_runner.run_hook(HookStage::Setup, hook_args)?;
+        let hook_args = &HookArgs {
+            params_dir: ParameterUtils::params_to_dirname(params),
+            ..hook_args.clone()
+        };
+        let mut hooks = self.hook_runner.run_hook(HookStage::Setup, hook_args)?;
         let mut results = Vec::with_capacity(total_runs);
 
         // Execute the benchmark runs
@@ -187,29 +405,36 @@ impl BenchmarkRunner {
             // Run each benchmark twice: uninstrumented then instrumented
             for i in 0..runs {
                 // Run uninstrumented version first
-                let result =
-                    self.execute_single_run(command, i * 2, commit, params, hook_args, false)?;
+                let result = self.execute_single_run(command, i * 2, params, hook_args, false)?;
                 results.push(result);
 
                 // Run instrumented version second
                 let result =
-                    self.execute_single_run(command, i * 2 + 1, commit, params, hook_args, true)?;
+                    self.execute_single_run(command, i * 2 + 1, params, hook_args, true)?;
                 results.push(result);
             }
         } else {
             // Run normally without instrumentation
             for i in 0..runs {
-                let result =
-                    self.execute_single_run(command, i, commit, params, hook_args, false)?;
+                let result = self.execute_single_run(command, i, params, hook_args, false)?;
                 results.push(result);
             }
         }
 
         // Run the cleanup script once after all benchmark runs
-        self.hook_runner.run_hook(HookStage::Cleanup, hook_args)?;
+        hooks.extend(self.hook_runner.run_hook(HookStage::Cleanup, hook_args)?);
+
+        if self.enable_profiling {
+            self.write_timeline(&results, params, hook_args)?;
+        }
 
         // Calculate statistics
         let summary = ResultAnalyzer::calculate_summary(&results);
+        if summary.is_none() {
+            warn!(
+                "No run completed with parameters {params:?}, leaving them out of the comparison"
+            );
+        }
 
         // Create the benchmark result
         let benchmark_result = BenchmarkResult {
@@ -217,6 +442,7 @@ impl BenchmarkRunner {
             parameters: params.clone(), // Copy the parameters into the result
             runs: results,
             summary,
+            hooks,
         };
 
         Ok(benchmark_result)
@@ -227,7 +453,6 @@ impl BenchmarkRunner {
         &self,
         command: &str,
         iteration: usize,
-        commit: &str,
         params: &HashMap<String, String>,
         hook_args: &HookArgs,
         use_perf_instrumentation: bool,
@@ -240,32 +465,131 @@ impl BenchmarkRunner {
             ..hook_args.clone()
         };
 
+        let network_datadir = get_network_datadir(&iter_args.tmp_data_dir, &iter_args.network);
+
+        if let Some(peer) = &self.peer {
+            peer.check_health()
+                .context("Peer is unhealthy, so runs would not be comparable")?;
+        }
+
         // Run prepare script before the benchmark run
-        self.hook_runner.run_hook(HookStage::Prepare, &iter_args)?;
+        let mut hooks = self.hook_runner.run_hook(HookStage::Prepare, &iter_args)?;
+
+        let limits = self
+            .limits
+            .as_ref()
+            .map(|limits| limits.resolve(params))
+            .transpose()?;
+        let cgroup = match &self.cgroup_root {
+            Some(root) => {
+                let cgroup = Cgroup::create(
+                    root,
+                    &format!("benchkit-{}-{iteration}", std::process::id()),
+                )?;
+                if let Some(limits) = &limits {
+                    info!("Applying resource limits: {limits:?}");
+                    cgroup.apply_limits(limits, device_for_path(&iter_args.tmp_data_dir))?;
+                }
+                Some(cgroup)
+            }
+            None => None,
+        };
 
-        let start = Instant::now();
-        let (output, profile_result) = if use_perf_instrumentation {
-            let (output, profile, _) =
-                self.execute_command_with_perf(command, iteration, commit, params)?;
-            (output, profile)
-        } else {
-            self.execute_command(command, iteration, commit, params)?
+        let prune_target = self
+            .prune
+            .as_ref()
+            .map(|prune| prune.resolve(params))
+            .transpose()?;
+        let blocks_dir = network_datadir.join("blocks");
+        let blocks_monitor =
+            prune_target.map(|_| BlocksMonitor::start(blocks_dir.clone(), BLOCKS_SAMPLE_INTERVAL));
+
+        let started = SystemTime::now();
+        let run = self.execute_command(
+            command,
+            &iter_args,
+            use_perf_instrumentation,
+            cgroup.as_ref(),
+        )?;
+
+        // Kill anything the command left behind before reading the accounting
+        let cgroup_stats = match cgroup {
+            Some(cgroup) => {
+                cgroup.kill()?;
+                let stats = cgroup.stats();
+                cgroup.teardown()?;
+                Some(stats)
+            }
+            None => None,
         };
 
-        // Stop timing (if we're not profiling, otherwise the profiler takes care of timing)
-        let duration = start.elapsed();
-        let duration_ms = if let Some(profile) = &profile_result {
-            // Use the duration from the profiler if available
-            profile.duration * 1000.0
-        } else {
-            duration.as_secs_f64() * 1000.0
+        let SupervisedRun {
+            output,
+            profile: profile_result,
+            stop_reason,
+            rusage,
+            log,
+            duration,
+            window,
+        } = run;
+        let mut status = RunStatus::from(stop_reason);
+        let mut failure = log.failure.clone();
+        if failure.is_some() {
+            // A fail rule fails the run even if the node then exited itself
+            status = RunStatus::Failed;
+        } else if stop_reason == StopReason::Exited && !output.status.success() {
+            status = RunStatus::Failed;
+            failure = Some(format!("Command exited with {}", output.status));
+        }
+        let windowed = self.measure_from.is_some() || self.measure_to.is_some();
+        if windowed && window.is_none() && status == RunStatus::Completed {
+            status = RunStatus::Failed;
+            failure = Some("Measurement markers were not reached".to_string());
+        }
+        let prune = match (prune_target, blocks_monitor) {
+            (Some(target), Some(monitor)) => Some(PruneResult::inspect(
+                target,
+                &blocks_dir,
+                monitor.finish()?,
+            )?),
+            _ => None,
         };
+        if let Some(reason) = prune.as_ref().and_then(PruneResult::failure) {
+            if status == RunStatus::Completed {
+                status = RunStatus::Failed;
+                failure = Some(reason);
+            }
+        }
+        if status != RunStatus::Completed {
+            warn!("Run {iteration} did not complete ({status:?}), it will be excluded from the summary");
+        }
+        // The measured window is the primary duration, with the full wall
+        // time kept as a metric
+        let duration_ms = window.unwrap_or(duration).as_secs_f64() * 1000.0;
+        // Marks, timers and captures of the log rules, and the milestones
+        // reached
+        let mut metrics: BTreeMap<String, f64> = log.metrics().collect();
+        let missed_milestones: Vec<u64> = self
+            .milestones
+            .iter()
+            .copied()
+            .filter(|height| !log.milestones.contains_key(height))
+            .collect();
+        for height in &missed_milestones {
+            warn!("Run {iteration} did not reach milestone height {height}");
+        }
+        if windowed {
+            metrics.insert("wall_time_ms".to_string(), duration.as_secs_f64() * 1000.0);
+        }
 
         // Record result
-        let run_result = RunResult {
+        let mut run_result = RunResult {
             iteration,
             duration_ms,
             exit_code: output.status.code().unwrap_or(-1),
+            status,
+            failure,
+            stop_reason,
             instrumentation: if use_perf_instrumentation {
                 InstrumentationType::PerfInstrumented
             } else {
@@ -278,184 +602,293 @@ impl BenchmarkRunner {
                 None
             },
             profile: profile_result,
+            // Recorded before the conclude hook clears the datadir
+            datadir_sizes: get_datadir_sizes(&network_datadir, &self.datadir_dirs),
+            cgroup: cgroup_stats,
+            limits,
+            rusage: Some(rusage),
+            metrics,
+            missed_milestones,
+            validation_timing: BTreeMap::new(),
+            chain_state: None,
+            prune,
+            hooks: Vec::new(),
         };
 
+        // A run cut short, or stopped by benchkit while the node was still
+        // validating, ends wherever it was stopped, so is not compared
+        if self.verify_chain_state
+            && status == RunStatus::Completed
+            && stop_reason == StopReason::Exited
+        {
+            let binary = iter_args.binary.replace("{commit}", &iter_args.commit);
+            // bitcoind refuses to open a pruned datadir without -prune
+            let extra_args: Vec<String> = prune_target
+                .map(|target| format!("-prune={target}"))
+                .into_iter()
+                .collect();
+            run_result.chain_state = Some(ChainState::capture(
+                &binary,
+                &iter_args.network,
+                &iter_args.tmp_data_dir,
+                &extra_args,
+            )?);
+        }
+
         // Run conclude script after the benchmark run
-        self.hook_runner.run_hook(HookStage::Conclude, &iter_args)?;
+        hooks.extend(self.hook_runner.run_hook(HookStage::Conclude, &iter_args)?);
+        run_result.hooks = hooks;
+
+        // The conclude hook moves debug.log into the run's output directory
+        let run_out_dir = self.run_out_dir(&iter_args);
+        let debug_log = run_out_dir.join("debug.log");
+        if debug_log.exists() {
+            let start_time = started.duration_since(UNIX_EPOCH)?.as_secs_f64();
+            self.record_sync_progress(&mut run_result, &debug_log, &run_out_dir, start_time)?;
+            if self.validation_timing {
+                Self::record_validation_timing(&mut run_result, &debug_log, start_time)?;
+            }
+        } else {
+            debug!("No debug.log at {}", debug_log.display());
+        }
 
         Ok(run_result)
     }
 
-    /// Execute a command with perf instrumentation
-    fn execute_command_with_perf(
-        &self,
-        command: &str,
-        iteration: usize,
-        commit: &str,
-        params: &HashMap<String, String>,
-    ) -> Result<(std::process::Output, Option<ProfileResult>, Option<PathBuf>)> {
-        // Create the output directory structure for this specific run
-        let params_dir = ParameterUtils::params_to_dirname(params);
-        let perf_out_dir = self
-            .out_dir
-            .join(commit)
-            .join(params_dir)
-            .join(iteration.to_string());
+    /// Record the per-block time spent in each validation phase, from the
+    /// `-debug=bench` lines logged during the run
+    fn record_validation_timing(
+        run_result: &mut RunResult,
+        debug_log: &Path,
+        start_time: f64,
+    ) -> Result<()> {
+        let timings = read_bench_timings(debug_log, start_time)?;
+        if timings.is_empty() {
+            warn!("No validation timing in debug.log, is bitcoind running with -debug=bench?");
+            return Ok(());
+        }
 
-        let perf_instrumentor = PerfInstrumentor::new(perf_out_dir);
-        // Wrap the command with perf
-        let (perf_command_vec, perf_data_path) = perf_instrumentor.wrap_command(command)?;
-        // Convert Vec<String> to a single command string for shell execution
-        let perf_command = perf_command_vec.join(" ");
+        for (phase, per_block) in timings {
+            let timing = PhaseTiming::from_block_times(&per_block);
+            run_result
+                .metrics
+                .insert(format!("validation_{phase}_ms"), timing.total_ms);
+            run_result.validation_timing.insert(phase, timing);
+        }
+        Ok(())
+    }
 
-        info!(
-            "Executing command with perf instrumentation: {}",
-            perf_command
-        );
+    /// Output directory of a single run, `<commit>/<params>/<iteration>`
+    fn run_out_dir(&self, hook_args: &HookArgs) -> PathBuf {
+        self.out_dir
+            .join(&hook_args.commit)
+            .join(&hook_args.params_dir)
+            .join(hook_args.iteration.to_string())
+    }
 
-        let child = self.launch_command_with_affinity(&perf_command)?;
-        let output = child
-            .wait_with_output()
-            .context("Failed to wait for perf command completion")?;
-        let perf_success = perf_instrumentor.finalize_perf_data()?;
-        if !perf_success {
-            warn!("perf instrumentation may have failed - no perf.data generated");
+    /// Write the sync progress of a run from its debug.log and record its
+    /// block and transaction throughput
+    fn record_sync_progress(
+        &self,
+        run_result: &mut RunResult,
+        debug_log: &Path,
+        run_out_dir: &Path,
+        start_time: f64,
+    ) -> Result<()> {
+        let progress = SyncProgress::from_tip_updates(&read_tip_updates(debug_log)?, start_time);
+        if progress.is_empty() {
+            debug!("No tip updates logged during the run");
+            return Ok(());
         }
+        progress.export(run_out_dir)?;
 
-        if !output.status.success() {
-            debug!(
-                "Perf command failed with status: {}",
-                output.status.code().unwrap_or(-1)
-            );
+        if let Some(rate) = progress.blocks_per_sec() {
+            run_result
+                .metrics
+                .insert("blocks_per_sec".to_string(), rate);
         }
+        if let Some(rate) = progress.tx_per_sec() {
+            run_result.metrics.insert("tx_per_sec".to_string(), rate);
+        }
+        Ok(())
+    }
 
-        Ok((output, None, Some(perf_data_path)))
+    /// Combine the profiles of all completed, uninstrumented runs into a
+    /// timeline written next to the iteration directories
+    fn write_timeline(
+        &self,
+        results: &[RunResult],
+        params: &HashMap<String, String>,
+        hook_args: &HookArgs,
+    ) -> Result<()> {
+        let cell_dir = self
+            .out_dir
+            .join(&hook_args.commit)
+            .join(ParameterUtils::params_to_dirname(params));
+
+        // Tip updates are read from the debug.log moved into each iteration
+        // directory by the conclude hook
+        let mut tips = Vec::new();
+        if self.timeline_alignment == TimelineAlignment::Height {
+            for result in results {
+                let path = cell_dir
+                    .join(result.iteration.to_string())
+                    .join("debug.log");
+                tips.push(read_tip_updates(&path).unwrap_or_else(|e| {
+                    warn!("No heights for run {}: {e:#}", result.iteration);
+                    Vec::new()
+                }));
+            }
+        }
+
+        let runs: Vec<_> = results
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| {
+                r.status == RunStatus::Completed
+                    && r.instrumentation == InstrumentationType::Uninstrumented
+            })
+            .filter_map(|(i, r)| Some((i, r.profile.as_ref()?)))
+            .map(|(i, profile)| match self.timeline_alignment {
+                TimelineAlignment::Time => Timeline::align_by_time(profile),
+                TimelineAlignment::Height => Timeline::align_by_height(profile, &tips[i]),
+            })
+            .filter(|samples| !samples.is_empty())
+            .collect();
+        if runs.is_empty() {
+            debug!("No profiled runs to combine into a timeline");
+            return Ok(());
+        }
+
+        let bucket = self
+            .timeline_bucket
+            .unwrap_or(match self.timeline_alignment {
+                TimelineAlignment::Time => self.profile_interval,
+                TimelineAlignment::Height => DEFAULT_HEIGHT_BUCKET,
+            });
+        let timeline = Timeline::aggregate(self.timeline_alignment, bucket as f64, &runs);
+        info!(
+            "Writing timeline of {} runs to {}",
+            timeline.runs,
+            cell_dir.display()
+        );
+        std::fs::create_dir_all(&cell_dir)?;
+        timeline.export(&cell_dir)
     }
 
     /// Launch a command with CPU affinity constraints
-    /// This is a helper function that can be used by both regular execution and profiling
-    fn launch_command_with_affinity(&self, command: &str) -> Result<std::process::Child> {
+    fn launch_command_with_affinity(
+        &self,
+        command: &str,
+        cgroup: Option<&Cgroup>,
+    ) -> Result<std::process::Child> {
         debug!("Launching command with affinity: {command}");
 
-        // Determine if we need to capture output
-        // We capture output if:
-        // 1. capture_output is true (for storing in results)
-        // 2. stop_on_log_pattern is configured (for monitoring)
-        // 3. We're not profiling (profiling doesn't capture output)
-        let should_capture =
-            !self.enable_profiling && (self.capture_output || self.stop_on_log_pattern.is_some());
-
         // Create a command executor with our benchmark settings
         let executor = CommandExecutor::builder()
             .name(command.to_string())
             .cpu_cores(self.benchmark_cores.clone())
             .process_group(true)
-            .capture_output(should_capture)
+            .capture_output(self.capture_output)
+            .cgroup(cgroup.map(Cgroup::path))
             .build()?;
 
         // Launch the command using the executor
         executor.launch_command("sh", &["-c", command])
     }
 
-    /// Execute a command and capture its output, optionally with profiling
+    /// Execute a command under a supervisor that handles profiling, the stop
+    /// pattern, stall detection and the timeout, optionally wrapped in perf
     fn execute_command(
         &self,
         command: &str,
-        iteration: usize,
-        commit: &str,
-        params: &HashMap<String, String>,
-    ) -> Result<(std::process::Output, Option<ProfileResult>)> {
-        // Automatically append -printtoconsole if stop_on_log_pattern is configured
-        // and the command doesn't already contain it
-        let final_command =
-            if self.stop_on_log_pattern.is_some() && !command.contains("-printtoconsole") {
-                let updated_command = format!("{command} -printtoconsole");
-                debug!(
-                "Automatically added -printtoconsole for log pattern matching: {updated_command}"
-            );
-                updated_command
-            } else {
-                command.to_string()
-            };
-
-        debug!("Executing command: {final_command}");
-
-        // Check for conflicts between profiling and stop_on_log_pattern
-        if self.enable_profiling && self.stop_on_log_pattern.is_some() {
-            warn!("Both profiling and stop_on_log_pattern are configured. Profiling takes precedence, stop_on_log_pattern will be ignored for this run.");
+        hook_args: &HookArgs,
+        use_perf_instrumentation: bool,
+        cgroup: Option<&Cgroup>,
+    ) -> Result<SupervisedRun> {
+        let mut final_command = command.to_string();
+
+        // Create a directory structure with commit/params/iteration
+        let run_out_dir = self.run_out_dir(hook_args);
+
+        let perf_instrumentor =
+            use_perf_instrumentation.then(|| PerfInstrumentor::new(run_out_dir.clone()));
+        if let Some(perf_instrumentor) = &perf_instrumentor {
+            // Wrap the command with perf
+            let (perf_command_vec, _) = perf_instrumentor.wrap_command(&final_command)?;
+            final_command = perf_command_vec.join(" ");
+            info!("Executing command with perf instrumentation: {final_command}");
         }
 
-        // If profiling is enabled, use the profiler to execute the command
-        if self.enable_profiling {
-            // Create a directory structure with commit/params/iteration
-            let params_dir = ParameterUtils::params_to_dirname(params);
-            let profile_out_dir = self
-                .out_dir
-                .join(commit)
-                .join(params_dir)
-                .join(iteration.to_string());
-            std::fs::create_dir_all(&profile_out_dir)?;
-
-            // Create the profiler with our benchmark cores
-            let mut profiler = Profiler::builder(&profile_out_dir)
-                .sample_interval(self.profile_interval)
-                .benchmark_cores(self.benchmark_cores.clone())
-                .build()?;
-
-            // Launch the command using our helper, which handles CPU affinity
+        let network_datadir = get_network_datadir(&hook_args.tmp_data_dir, &hook_args.network);
+        let profiler = if self.enable_profiling {
+            std::fs::create_dir_all(&run_out_dir)?;
             info!("Profiling command: {final_command}");
-            let child = self.launch_command_with_affinity(&final_command)?;
-            let profile_result = profiler.profile_process(&final_command, child)?;
+            Some(
+                Profiler::builder(&run_out_dir)
+                    .sample_interval(self.profile_interval)
+                    .benchmark_cores(self.benchmark_cores.clone())
+                    .datadir_dirs(&network_datadir, self.datadir_dirs.clone())
+                    .host_monitoring(&hook_args.tmp_data_dir, self.interference_threshold)
+                    .build()?,
+            )
+        } else {
+            None
+        };
 
-            // Make an Output manually for profile
-            let output = std::process::Output {
-                status: ExitStatusExt::from_raw(profile_result.exit_code),
-                stdout: Vec::new(),
-                stderr: Vec::new(),
-            };
+        let rpc_client = RpcClient::new(DEFAULT_RPC_PORT, &network_datadir.join(".cookie"))?;
+        let rpc_collector = match self.rpc_interval {
+            Some(interval) => {
+                std::fs::create_dir_all(&run_out_dir)?;
+                Some(RpcCollector::new(
+                    rpc_client.clone(),
+                    Duration::from_secs(interval),
+                    &run_out_dir,
+                ))
+            }
+            None => None,
+        };
+        if let Some(height) = self.stop_at_height {
+            info!("Stopping at height {height}");
+        }
 
-            return Ok((output, Some(profile_result)));
+        // Log rules follow debug.log on disk, so the command is unchanged
+        let debug_log = network_datadir.join("debug.log");
+        for rule in &self.log_rules {
+            info!(
+                "Monitoring {} for pattern: {} ({:?})",
+                debug_log.display(),
+                rule.pattern,
+                rule.action
+            );
         }
 
-        // For non-profiled commands, launch and potentially monitor
-        let mut child = self.launch_command_with_affinity(&final_command)?;
-
-        if let Some(pattern) = &self.stop_on_log_pattern {
-            info!("Monitoring command output for pattern: {pattern}");
-            let mut monitor = LogMonitor::start_monitoring(&mut child, pattern.clone())?;
-            // Wait for either pattern match or process exit
-            let pattern_matched = monitor
-                .wait_for_match_or_exit(&mut child, std::time::Duration::from_millis(100))?;
-
-            if pattern_matched {
-                info!("Pattern matched, terminating process");
-                match child.kill() {
-                    Ok(_) => debug!("Process terminated successfully"),
-                    Err(e) => warn!("Failed to terminate process: {e}"),
-                }
+        // Built before launching so earlier debug.log lines are skipped
+        let supervisor = ProcessSupervisor::builder()
+            .profiler(profiler)
+            .rpc_collector(rpc_collector)
+            .rpc_client(Some(rpc_client))
+            .stop_at_height(self.stop_at_height)
+            .measure(self.measure_from.clone(), self.measure_to.clone())
+            .log_rules(self.log_rules.clone())
+            .milestones(self.milestones.clone())
+            .log_file(Some(debug_log))
+            .capture_output(self.capture_output)
+            .stall_detection(self.stall_config)
+            .timeout(self.timeout)
+            .build();
 
-                // Also try to terminate any child processes via process group
-                if let Some(pgid) = child.id().checked_neg() {
-                    unsafe {
-                        libc::kill(pgid as i32, libc::SIGTERM);
-                    }
-                }
-            }
-        }
+        debug!("Executing command: {final_command}");
+        let child = self.launch_command_with_affinity(&final_command, cgroup)?;
+        let run = supervisor.supervise(&final_command, child)?;
 
-        let output = child
-            .wait_with_output()
-            .context("Failed to wait for command completion")?;
-        if !output.status.success() {
-            debug!(
-                "Command failed with status: {}",
-                output.status.code().unwrap_or(-1)
-            );
-            // We don't return an error here because we want to capture benchmark failures
-            // and include them in the results
+        if let Some(perf_instrumentor) = &perf_instrumentor {
+            if !perf_instrumentor.finalize_perf_data()? {
+                warn!("perf instrumentation may have failed - no perf.data generated");
+            }
         }
 
-        Ok((output, None))
+        Ok(run)
     }
 
     /// Run all parameter combinations for a command template
@@ -91,7 +91,7 @@ impl Builder {
         // If we're using a remote repository, ensure it's available
         let source_dir = if let Some(repo_manager) = &mut self.repo_manager {
             let repo_path = repo_manager.ensure_repository_available()?;
-            repo_manager.validate_commits(&self.config.bench.global.commits)?;
+            repo_manager.validate_commits(&self.config.bench.required_commits())?;
             repo_path
         } else {
             // Using a local repository
@@ -110,8 +110,8 @@ impl Builder {
         // Get the initial reference to restore later
         let initial_ref = self.get_initial_ref(&source_dir)?;
 
-        // Build all commits up-front
-        for commit in &self.config.bench.global.commits {
+        // Build all commits up-front, including those benchmarks upgrade from
+        for commit in &self.config.bench.required_commits() {
             if !binary_exists(&self.config.app.bin_dir, commit) {
                 info!("Building binary for commit {commit}");
                 self.build_commit(&source_dir, commit)?;
@@ -184,7 +184,7 @@ impl Builder {
         // If we're using a remote repository, ensure it's available
         let source_dir = if let Some(repo_manager) = &mut self.repo_manager {
             let repo_path = repo_manager.ensure_repository_available()?;
-            repo_manager.validate_commits(&self.config.bench.global.commits)?;
+            repo_manager.validate_commits(&self.config.bench.required_commits())?;
             repo_path
         } else {
             // For local repos, use the path directly
@@ -195,7 +195,7 @@ impl Builder {
         self.check_clean_worktree(&source_dir)?;
         let initial_ref = self.get_initial_ref(&source_dir)?;
 
-        for commit in &self.config.bench.global.commits {
+        for commit in &self.config.bench.required_commits() {
             self.checkout_commit(&source_dir, commit)?;
             self.test_patches(&source_dir)?;
         }
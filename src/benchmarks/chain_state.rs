@@ -0,0 +1,303 @@
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::benchmarks::results::{BenchmarkResult, RunStatus};
+use crate::benchmarks::rpc::RpcClient;
+use crate::benchmarks::supervisor::StopReason;
+use crate::benchmarks::utils::{build_bitcoind_base_args, get_network_datadir, DEFAULT_RPC_PORT};
+use crate::command::CommandExecutor;
+
+/// How long the relaunched node is given to start answering RPC calls
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// How long hashing the UTXO set may take
+const QUERY_TIMEOUT: Duration = Duration::from_secs(3600);
+
+/// The chain a run ended on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainState {
+    /// Hash of the best block
+    pub best_block_hash: String,
+    /// Height of the best block
+    pub height: u64,
+    /// Serialized hash of the UTXO set, from `gettxoutsetinfo`
+    pub utxo_set_hash: String,
+}
+
+impl ChainState {
+    /// Query the chain state of a running node
+    pub fn query(client: &RpcClient) -> Result<Self> {
+        let best_block_hash = client
+            .call("getbestblockhash", json!([]))?
+            .as_str()
+            .context("Invalid getbestblockhash response")?
+            .to_string();
+        let height = client
+            .call("getblockcount", json!([]))?
+            .as_u64()
+            .context("Invalid getblockcount response")?;
+        let utxo_set_hash = utxo_set_hash(&client.call("gettxoutsetinfo", json!([]))?)
+            .context("gettxoutsetinfo response has no UTXO set hash")?;
+
+        Ok(Self {
+            best_block_hash,
+            height,
+            utxo_set_hash,
+        })
+    }
+
+    /// Start `binary` on the datadir a run left behind, without connecting to
+    /// any peers, and query its chain state before shutting it down again.
+    /// `extra_args` are those the datadir must be opened with, such as
+    /// `-prune`.
+    pub fn capture(
+        binary: &str,
+        network: &str,
+        datadir: &Path,
+        extra_args: &[String],
+    ) -> Result<Self> {
+        let args = Self::capture_args(network, datadir, extra_args);
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+        info!("Relaunching {binary} to record the chain state");
+        let mut child = CommandExecutor::new().launch_command(binary, &args)?;
+        let cookie = get_network_datadir(datadir, network).join(".cookie");
+        let client = RpcClient::with_timeout(DEFAULT_RPC_PORT, &cookie, QUERY_TIMEOUT)?;
+
+        let state = client
+            .wait_until_ready(&mut child, STARTUP_TIMEOUT)
+            .and_then(|()| Self::query(&client));
+
+        // Always shut the node down, even if it could not be queried
+        if let Err(e) = client.call("stop", json!([])) {
+            debug!("Failed to stop node over RPC, killing it: {e:#}");
+            child.kill().ok();
+        }
+        child.wait().context("Failed to wait for node to stop")?;
+
+        let state = state?;
+        info!(
+            "Chain state: height {} best block {} UTXO set {}",
+            state.height, state.best_block_hash, state.utxo_set_hash
+        );
+        Ok(state)
+    }
+
+    /// Arguments the node is relaunched with to capture the chain state
+    fn capture_args(network: &str, datadir: &Path, extra_args: &[String]) -> Vec<String> {
+        let mut args = build_bitcoind_base_args(network, datadir, "");
+        // Keep the run's debug.log as it was
+        args.extend(
+            [
+                "-connect=0",
+                "-listen=0",
+                "-nodebuglogfile",
+                "-printtoconsole=0",
+            ]
+            .map(String::from),
+        );
+        args.extend(extra_args.iter().cloned());
+        args
+    }
+
+    /// Check that all completed runs with the same parameters, other than the
+    /// commit, ended on the same chain state
+    ///
+    /// Only runs where the node exited by itself, as with `-stopatheight`,
+    /// are compared. A node stopped on a log pattern or height keeps
+    /// validating until it shuts down, so where it ends depends on timing.
+    pub fn verify_consistent(results: &[BenchmarkResult]) -> Result<()> {
+        let completed = || {
+            results.iter().flat_map(|result| {
+                result
+                    .runs
+                    .iter()
+                    .filter(|run| run.status == RunStatus::Completed)
+                    .map(move |run| (&result.parameters, run))
+            })
+        };
+        let stopped = completed()
+            .filter(|(_, run)| run.stop_reason != StopReason::Exited)
+            .count();
+        if stopped > 0 {
+            warn!(
+                "Not comparing the chain state of {stopped} runs stopped by benchkit, \
+                 which end on a height that depends on timing"
+            );
+        }
+        let states = completed()
+            .filter(|(_, run)| run.stop_reason == StopReason::Exited)
+            .filter_map(|(params, run)| Some((params, run.iteration, run.chain_state.as_ref()?)));
+
+        let divergences = divergences(states);
+        if !divergences.is_empty() {
+            anyhow::bail!(
+                "Runs ended on different chain states:\n{}",
+                divergences.join("\n")
+            );
+        }
+        info!("All runs ended on the same chain state");
+        Ok(())
+    }
+}
+
+/// The serialized UTXO set hash, which is named after its version
+fn utxo_set_hash(info: &Value) -> Option<String> {
+    info.as_object()?
+        .iter()
+        .find(|(key, _)| key.starts_with("hash_serialized"))
+        .and_then(|(_, hash)| hash.as_str())
+        .map(str::to_string)
+}
+
+/// Commit, iteration and chain state of a run
+type RunState<'a> = (String, usize, &'a ChainState);
+
+/// Describe each run whose chain state differs from the first run with the
+/// same parameters, ignoring the commit
+fn divergences<'a>(
+    states: impl Iterator<Item = (&'a HashMap<String, String>, usize, &'a ChainState)>,
+) -> Vec<String> {
+    let mut groups: BTreeMap<BTreeMap<&String, &String>, Vec<RunState>> = BTreeMap::new();
+    for (params, iteration, state) in states {
+        let key = params.iter().filter(|(k, _)| *k != "commit").collect();
+        let commit = params
+            .get("commit")
+            .cloned()
+            .unwrap_or_else(|| "default".to_string());
+        groups
+            .entry(key)
+            .or_default()
+            .push((commit, iteration, state));
+    }
+
+    let mut divergences = Vec::new();
+    for runs in groups.values() {
+        let (ref_commit, ref_iteration, reference) = &runs[0];
+        for (commit, iteration, state) in &runs[1..] {
+            if state != reference {
+                divergences.push(format!(
+                    "  {commit} run {iteration} ({}, {}, {}) differs from {ref_commit} run {ref_iteration} ({}, {}, {})",
+                    state.height,
+                    state.best_block_hash,
+                    state.utxo_set_hash,
+                    reference.height,
+                    reference.best_block_hash,
+                    reference.utxo_set_hash
+                ));
+            }
+        }
+    }
+    divergences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::benchmarks::results::RunResult;
+
+    #[test]
+    fn test_capture_args() {
+        let datadir = Path::new("/tmp/data");
+        let args = ChainState::capture_args("signet", datadir, &[]);
+        assert!(args.contains(&"-connect=0".to_string()));
+        assert!(!args.iter().any(|arg| arg.starts_with("-prune")));
+
+        // A pruned datadir cannot be opened without -prune
+        let args = ChainState::capture_args("signet", datadir, &["-prune=1000".to_string()]);
+        assert_eq!(args.last().map(String::as_str), Some("-prune=1000"));
+    }
+
+    #[test]
+    fn test_verify_only_exited_runs() {
+        let run = |iteration: usize, stop_reason: &str, utxo: &str| -> RunResult {
+            serde_json::from_value(json!({
+                "iteration": iteration,
+                "duration_ms": 1000.0,
+                "exit_code": 0,
+                "stop_reason": stop_reason,
+                "instrumentation": "Uninstrumented",
+                "chain_state": {
+                    "best_block_hash": "hash",
+                    "height": 100,
+                    "utxo_set_hash": utxo,
+                },
+            }))
+            .unwrap()
+        };
+        let result = |commit: &str, runs: Vec<RunResult>| BenchmarkResult {
+            command: "bitcoind".to_string(),
+            parameters: HashMap::from([("commit".to_string(), commit.to_string())]),
+            summary: None,
+            runs,
+            hooks: Vec::new(),
+        };
+
+        // Runs stopped on a log pattern or height may end anywhere
+        let results = [
+            result(
+                "a",
+                vec![run(0, "exited", "aaaa"), run(1, "height", "bbbb")],
+            ),
+            result(
+                "b",
+                vec![run(0, "exited", "aaaa"), run(1, "log_pattern", "cccc")],
+            ),
+        ];
+        ChainState::verify_consistent(&results).unwrap();
+
+        let results = [
+            result("a", vec![run(0, "exited", "aaaa")]),
+            result("b", vec![run(0, "exited", "bbbb")]),
+        ];
+        assert!(ChainState::verify_consistent(&results).is_err());
+    }
+
+    #[test]
+    fn test_divergences() {
+        let state = |height, utxo: &str| ChainState {
+            best_block_hash: format!("hash{height}"),
+            height,
+            utxo_set_hash: utxo.to_string(),
+        };
+        let params = |commit: &str, dbcache: &str| {
+            HashMap::from([
+                ("commit".to_string(), commit.to_string()),
+                ("dbcache".to_string(), dbcache.to_string()),
+            ])
+        };
+        let (a450, b450, a1000, b1000) = (
+            params("a", "450"),
+            params("b", "450"),
+            params("a", "1000"),
+            params("b", "1000"),
+        );
+        let good = state(100, "aaaa");
+        let bad = state(100, "bbbb");
+
+        // Different parameters are never compared with each other
+        let consistent = [
+            (&a450, 0, &good),
+            (&b450, 0, &good),
+            (&a1000, 0, &bad),
+            (&b1000, 0, &bad),
+        ];
+        assert!(divergences(consistent.into_iter()).is_empty());
+
+        let diverged = [(&a450, 0, &good), (&a450, 1, &good), (&b450, 0, &bad)];
+        let found = divergences(diverged.into_iter());
+        assert_eq!(found.len(), 1);
+        assert!(found[0].contains("b run 0"));
+        assert!(found[0].contains("a run 0"));
+
+        assert_eq!(
+            utxo_set_hash(&json!({"height": 100, "hash_serialized_3": "abcd"})),
+            Some("abcd".to_string())
+        );
+    }
+}
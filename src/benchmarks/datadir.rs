@@ -0,0 +1,156 @@
+use anyhow::{Context, Result};
+use log::{debug, info};
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+/// Files that are not restored, so each run starts with its own log and
+/// without the state of a node that may have been running on the source
+const SKIPPED_FILES: &[&str] = &["debug.log", ".lock", ".cookie", "bitcoind.pid"];
+
+/// Number of files restored into a datadir and how many of them were cloned
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RestoreStats {
+    pub files: usize,
+    pub cloned: usize,
+}
+
+/// Restore `dst` from the contents of `source`, cloning files with FICLONE
+/// where the filesystem supports it and copying them otherwise. `dst` should
+/// be empty.
+pub fn restore_datadir(source: &Path, dst: &Path) -> Result<RestoreStats> {
+    let mut stats = RestoreStats::default();
+    restore_dir(source, dst, &mut stats)?;
+    info!(
+        "Restored {} files from {} ({} cloned, {} copied)",
+        stats.files,
+        source.display(),
+        stats.cloned,
+        stats.files - stats.cloned
+    );
+    Ok(stats)
+}
+
+fn restore_dir(source: &Path, dst: &Path, stats: &mut RestoreStats) -> Result<()> {
+    fs::create_dir_all(dst).with_context(|| format!("Failed to create {}", dst.display()))?;
+    for entry in
+        fs::read_dir(source).with_context(|| format!("Failed to read {}", source.display()))?
+    {
+        let entry = entry?;
+        let from = entry.path();
+        let to = dst.join(entry.file_name());
+        let file_type = entry.file_type()?;
+
+        if file_type.is_dir() {
+            restore_dir(&from, &to, stats)?;
+        } else if file_type.is_symlink() {
+            std::os::unix::fs::symlink(fs::read_link(&from)?, &to)
+                .with_context(|| format!("Failed to link {}", to.display()))?;
+        } else if !SKIPPED_FILES.iter().any(|name| entry.file_name() == *name) {
+            if clone_file(&from, &to)? {
+                stats.cloned += 1;
+            } else {
+                fs::copy(&from, &to).with_context(|| {
+                    format!("Failed to copy {} to {}", from.display(), to.display())
+                })?;
+            }
+            stats.files += 1;
+        }
+    }
+    Ok(())
+}
+
+/// Clone `from` to `to` with FICLONE, sharing extents with the source.
+/// Returns false, leaving nothing at `to`, if the filesystem cannot clone.
+#[cfg(target_os = "linux")]
+fn clone_file(from: &Path, to: &Path) -> Result<bool> {
+    use std::os::fd::AsRawFd;
+
+    let src = File::open(from).with_context(|| format!("Failed to open {}", from.display()))?;
+    let dst = File::create(to).with_context(|| format!("Failed to create {}", to.display()))?;
+    if unsafe { libc::ioctl(dst.as_raw_fd(), libc::FICLONE, src.as_raw_fd()) } == 0 {
+        fs::set_permissions(to, src.metadata()?.permissions())?;
+        return Ok(true);
+    }
+
+    let err = std::io::Error::last_os_error();
+    debug!("Cannot clone {}: {err}", from.display());
+    drop(dst);
+    fs::remove_file(to)?;
+    Ok(false)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn clone_file(_from: &Path, _to: &Path) -> Result<bool> {
+    Ok(false)
+}
+
+/// Fingerprint of the files in a datadir, from the path, size and
+/// modification time of each, used to check it has not been modified
+pub fn fingerprint(dir: &Path) -> Result<u64> {
+    let mut entries = Vec::new();
+    collect_entries(dir, dir, &mut entries)?;
+    entries.sort();
+
+    let mut hasher = DefaultHasher::new();
+    entries.hash(&mut hasher);
+    Ok(hasher.finish())
+}
+
+fn collect_entries(
+    root: &Path,
+    dir: &Path,
+    entries: &mut Vec<(String, u64, i64, i64)>,
+) -> Result<()> {
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        if meta.is_dir() {
+            collect_entries(root, &entry.path(), entries)?;
+        } else if !SKIPPED_FILES.iter().any(|name| entry.file_name() == *name) {
+            let path = entry.path();
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            entries.push((
+                relative.display().to_string(),
+                meta.len(),
+                meta.mtime(),
+                meta.mtime_nsec(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restore_and_fingerprint() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source");
+        fs::create_dir_all(source.join("signet/chainstate")).unwrap();
+        fs::write(source.join("signet/chainstate/000001.ldb"), "coins").unwrap();
+        fs::write(source.join("signet/debug.log"), "old log").unwrap();
+        let before = fingerprint(&source).unwrap();
+
+        let dst = dir.path().join("data");
+        let stats = restore_datadir(&source, &dst).unwrap();
+        assert_eq!(stats.files, 1);
+        assert_eq!(
+            fs::read_to_string(dst.join("signet/chainstate/000001.ldb")).unwrap(),
+            "coins"
+        );
+        assert!(!dst.join("signet/debug.log").exists());
+
+        // Changes to the restored copy or the log leave the source unchanged
+        fs::write(dst.join("signet/chainstate/000001.ldb"), "spent").unwrap();
+        fs::write(source.join("signet/debug.log"), "new log").unwrap();
+        assert_eq!(fingerprint(&source).unwrap(), before);
+
+        fs::write(source.join("signet/chainstate/000002.ldb"), "more").unwrap();
+        assert_ne!(fingerprint(&source).unwrap(), before);
+    }
+}
@@ -0,0 +1,367 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+/// A new chain tip reported by an `UpdateTip` line in debug.log
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TipUpdate {
+    /// Time of the log line, in seconds since the Unix epoch
+    pub time: f64,
+    /// Height of the new tip
+    pub height: u64,
+    /// Total number of transactions in the chain up to the tip
+    pub tx: Option<u64>,
+    /// Block timestamp of the tip
+    pub date: Option<String>,
+    /// Estimated verification progress, from 0 to 1
+    pub progress: Option<f64>,
+    /// Size of the coins cache in MiB
+    pub cache_mib: Option<f64>,
+    /// Number of coins in the cache
+    pub cache_txo: Option<u64>,
+}
+
+/// A point of the sync-progress timeline of a run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProgressPoint {
+    /// Seconds since the start of the run
+    pub elapsed: f64,
+    pub height: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tx: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub progress: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_mib: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_txo: Option<u64>,
+}
+
+/// Chain tip updates logged during a run, against the time since it started
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncProgress {
+    pub points: Vec<ProgressPoint>,
+}
+
+impl SyncProgress {
+    /// Build the timeline from the tip updates logged at or after
+    /// `start_time` (seconds since the Unix epoch)
+    pub fn from_tip_updates(tips: &[TipUpdate], start_time: f64) -> Self {
+        // debug.log timestamps may only have whole seconds
+        let start = start_time.floor();
+        let points = tips
+            .iter()
+            .filter(|tip| tip.time >= start)
+            .map(|tip| ProgressPoint {
+                elapsed: (tip.time - start_time).max(0.0),
+                height: tip.height,
+                tx: tip.tx,
+                date: tip.date.clone(),
+                progress: tip.progress,
+                cache_mib: tip.cache_mib,
+                cache_txo: tip.cache_txo,
+            })
+            .collect();
+        Self { points }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Blocks connected per second between the first and last tip update
+    pub fn blocks_per_sec(&self) -> Option<f64> {
+        let (first, last) = (self.points.first()?, self.points.last()?);
+        rate(
+            last.height.checked_sub(first.height)?,
+            last.elapsed - first.elapsed,
+        )
+    }
+
+    /// Transactions connected per second between the first and last tip
+    /// update
+    pub fn tx_per_sec(&self) -> Option<f64> {
+        let (first, last) = (self.points.first()?, self.points.last()?);
+        rate(
+            last.tx?.checked_sub(first.tx?)?,
+            last.elapsed - first.elapsed,
+        )
+    }
+
+    /// Write the timeline to `progress.json` and `progress.csv` in `dir`
+    pub fn export(&self, dir: &Path) -> Result<()> {
+        let json_path = dir.join("progress.json");
+        let json_data =
+            serde_json::to_string_pretty(self).context("Failed to serialize sync progress")?;
+        std::fs::write(&json_path, json_data)
+            .with_context(|| format!("Failed to write {}", json_path.display()))?;
+
+        let csv_path = dir.join("progress.csv");
+        self.export_csv(&csv_path)
+            .with_context(|| format!("Failed to write {}", csv_path.display()))
+    }
+
+    fn export_csv(&self, path: &Path) -> Result<()> {
+        fn field<T: ToString>(value: &Option<T>) -> String {
+            value.as_ref().map(T::to_string).unwrap_or_default()
+        }
+
+        let mut file = File::create(path)?;
+        writeln!(file, "elapsed,height,tx,date,progress,cache_mib,cache_txo")?;
+        for point in &self.points {
+            writeln!(
+                file,
+                "{},{},{},{},{},{},{}",
+                point.elapsed,
+                point.height,
+                field(&point.tx),
+                field(&point.date),
+                field(&point.progress),
+                field(&point.cache_mib),
+                field(&point.cache_txo)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn rate(count: u64, seconds: f64) -> Option<f64> {
+    (seconds > 0.0).then(|| count as f64 / seconds)
+}
+
+/// Parse the timestamp at the start of a debug.log line, e.g.
+/// `2025-01-31T12:34:56Z` or `2025-01-31T12:34:56.123456Z`, returning seconds
+/// since the Unix epoch
+pub fn parse_timestamp(line: &str) -> Option<f64> {
+    let stamp = line.split_whitespace().next()?.strip_suffix('Z')?;
+    let (date, time) = stamp.split_once('T')?;
+
+    let mut date_parts = date.splitn(3, '-');
+    let year: i64 = date_parts.next()?.parse().ok()?;
+    let month: i64 = date_parts.next()?.parse().ok()?;
+    let day: i64 = date_parts.next()?.parse().ok()?;
+
+    let mut time_parts = time.splitn(3, ':');
+    let hours: i64 = time_parts.next()?.parse().ok()?;
+    let minutes: i64 = time_parts.next()?.parse().ok()?;
+    let seconds: f64 = time_parts.next()?.parse().ok()?;
+
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+    Some((days * 86400 + hours * 3600 + minutes * 60) as f64 + seconds)
+}
+
+/// Number of days from 1970-01-01 to the given proleptic Gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Parse an `UpdateTip` line, e.g.
+///
+/// ```text
+/// 2025-01-31T12:34:56Z UpdateTip: new best=0000... height=840000 version=0x20000000
+///   log2_work=94.8 tx=1000000000 date='2024-04-20T00:09:27Z' progress=0.99 cache=120.3MiB(900000txo)
+/// ```
+///
+/// Only the timestamp and height are required; other fields missing from
+/// older versions are left empty.
+pub fn parse_update_tip(line: &str) -> Option<TipUpdate> {
+    let (_, fields) = line.split_once("UpdateTip: ")?;
+    let mut update = TipUpdate {
+        time: parse_timestamp(line)?,
+        ..Default::default()
+    };
+    let mut height = None;
+
+    for field in fields.split_whitespace() {
+        let Some((key, value)) = field.split_once('=') else {
+            continue;
+        };
+        match key {
+            "height" => height = value.parse().ok(),
+            "tx" => update.tx = value.parse().ok(),
+            "date" => update.date = Some(value.trim_matches('\'').to_string()),
+            "progress" => update.progress = value.parse().ok(),
+            "cache" => {
+                // e.g. 120.3MiB(900000txo)
+                if let Some((mib, txo)) = value.split_once("MiB(") {
+                    update.cache_mib = mib.parse().ok();
+                    update.cache_txo = txo.strip_suffix("txo)").and_then(|t| t.parse().ok());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    update.height = height?;
+    Some(update)
+}
+
+/// Read all tip updates from a debug.log, in the order they were logged
+pub fn read_tip_updates(path: &Path) -> Result<Vec<TipUpdate>> {
+    let mut updates = Vec::new();
+    for_each_line(path, |line| {
+        if let Some(update) = parse_update_tip(line) {
+            updates.push(update);
+        }
+    })?;
+    Ok(updates)
+}
+
+/// Matches the per-block timing lines logged with `-debug=bench`, e.g.
+/// `    - Verify 1234 txins: 12.34ms (0.010ms/txin) [56.78s (9.87ms/blk)]`
+const BENCH_LINE_PATTERN: &str = r"^(?:\[bench\] )?\s*- ([A-Za-z][A-Za-z0-9 ]*?): ([0-9.]+)ms";
+
+/// Parse a `-debug=bench` timing line into its phase and the time spent in
+/// it for one block, in milliseconds. The phase is the label in snake case
+/// with any counts dropped, so `Connect 12 transactions` becomes
+/// `connect_transactions`.
+fn parse_bench_line(regex: &Regex, line: &str) -> Option<(String, f64)> {
+    // Drop the timestamp and any thread name
+    let message = match parse_timestamp(line) {
+        Some(_) => line.split_once(' ')?.1,
+        None => line,
+    };
+    let message = match message.strip_prefix('[') {
+        Some(rest) if !rest.starts_with("bench]") => rest.split_once("] ")?.1,
+        _ => message,
+    };
+
+    let captures = regex.captures(message)?;
+    let phase = captures[1]
+        .split_whitespace()
+        .filter(|word| !word.chars().all(|c| c.is_ascii_digit()))
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("_");
+    Some((phase, captures[2].parse().ok()?))
+}
+
+/// Read the per-block times of each validation phase logged with
+/// `-debug=bench` at or after `start_time` (seconds since the Unix epoch)
+pub fn read_bench_timings(path: &Path, start_time: f64) -> Result<BTreeMap<String, Vec<f64>>> {
+    let regex = Regex::new(BENCH_LINE_PATTERN)?;
+    let start = start_time.floor();
+    let mut timings: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+    for_each_line(path, |line| {
+        if parse_timestamp(line).is_some_and(|time| time < start) {
+            return;
+        }
+        if let Some((phase, ms)) = parse_bench_line(&regex, line) {
+            timings.entry(phase).or_default().push(ms);
+        }
+    })?;
+    Ok(timings)
+}
+
+/// Call `f` with each line of a log file, replacing invalid UTF-8
+fn for_each_line(path: &Path, mut f: impl FnMut(&str)) -> Result<()> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    for line in BufReader::new(file).split(b'\n') {
+        let line = line.with_context(|| format!("Failed to read {}", path.display()))?;
+        f(&String::from_utf8_lossy(&line));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_update_tip() {
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z foo"), Some(0.0));
+        assert_eq!(
+            parse_timestamp("2024-02-29T12:00:01.500000Z foo"),
+            Some(1709208001.5)
+        );
+        assert_eq!(parse_timestamp("not a timestamp"), None);
+
+        let line = "2024-02-29T12:00:01Z UpdateTip: new best=00000000000000000002 \
+                    height=834000 version=0x20000000 log2_work=94.7 tx=970000000 \
+                    date='2024-03-01T00:00:00Z' progress=0.999 cache=12.3MiB(90000txo)";
+        assert_eq!(
+            parse_update_tip(line),
+            Some(TipUpdate {
+                time: 1709208001.0,
+                height: 834000,
+                tx: Some(970000000),
+                date: Some("2024-03-01T00:00:00Z".to_string()),
+                progress: Some(0.999),
+                cache_mib: Some(12.3),
+                cache_txo: Some(90000),
+            })
+        );
+        assert_eq!(
+            parse_update_tip("2024-02-29T12:00:01Z Loaded best chain: height=834000"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_bench_line() {
+        let regex = Regex::new(BENCH_LINE_PATTERN).unwrap();
+        let parse = |line| parse_bench_line(&regex, line);
+
+        assert_eq!(
+            parse("2024-02-29T12:00:01Z   - Load block from disk: 0.52ms"),
+            Some(("load_block_from_disk".to_string(), 0.52))
+        );
+        assert_eq!(
+            parse("2024-02-29T12:00:01Z [bench]       - Connect 2816 transactions: 12.85ms (0.005ms/tx, 0.002ms/txin) [1.80s (9.48ms/blk)]"),
+            Some(("connect_transactions".to_string(), 12.85))
+        );
+        assert_eq!(
+            parse("2024-02-29T12:00:01Z [msghand]     - Verify 6214 txins: 13.10ms (0.002ms/txin) [1.85s (9.74ms/blk)]"),
+            Some(("verify_txins".to_string(), 13.1))
+        );
+        assert_eq!(
+            parse("- Connect block: 15.21ms [2.95s (15.53ms/blk)]"),
+            Some(("connect_block".to_string(), 15.21))
+        );
+        assert_eq!(
+            parse("2024-02-29T12:00:01Z Opened LevelDB successfully"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_sync_progress() {
+        let tip = |time: f64, height: u64, tx: u64| TipUpdate {
+            time,
+            height,
+            tx: Some(tx),
+            ..Default::default()
+        };
+        // The first update was logged before the run started
+        let tips = [
+            tip(999.0, 90, 900),
+            tip(1000.0, 100, 1000),
+            tip(1010.0, 150, 3000),
+            tip(1020.0, 200, 6000),
+        ];
+
+        let progress = SyncProgress::from_tip_updates(&tips, 1000.0);
+        assert_eq!(progress.points.len(), 3);
+        assert_eq!(progress.points[2].elapsed, 20.0);
+        assert_eq!(progress.blocks_per_sec(), Some(5.0));
+        assert_eq!(progress.tx_per_sec(), Some(250.0));
+
+        let progress = SyncProgress::from_tip_updates(&tips[..1], 0.0);
+        assert_eq!(progress.blocks_per_sec(), None);
+    }
+}
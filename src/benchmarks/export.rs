@@ -62,14 +62,16 @@ impl ResultExporter {
                 run.iteration, run.duration_ms, run.exit_code
             )?;
         }
-        // summary
-        writeln!(file)?;
-        writeln!(file, "Summary:")?;
-        writeln!(file, "min,{:.2}", result.summary.min)?;
-        writeln!(file, "max,{:.2}", result.summary.max)?;
-        writeln!(file, "mean,{:.2}", result.summary.mean)?;
-        writeln!(file, "median,{:.2}", result.summary.median)?;
-        writeln!(file, "std_dev,{:.2}", result.summary.std_dev)?;
+        // summary, if any run completed
+        if let Some(summary) = &result.summary {
+            writeln!(file)?;
+            writeln!(file, "Summary:")?;
+            writeln!(file, "min,{:.2}", summary.min)?;
+            writeln!(file, "max,{:.2}", summary.max)?;
+            writeln!(file, "mean,{:.2}", summary.mean)?;
+            writeln!(file, "median,{:.2}", summary.median)?;
+            writeln!(file, "std_dev,{:.2}", summary.std_dev)?;
+        }
 
         Ok(())
     }
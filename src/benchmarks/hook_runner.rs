@@ -1,13 +1,20 @@
-use anyhow::Result;
-use log::info;
+use anyhow::{Context, Result};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
+use std::time::Instant;
 
 use crate::benchmarks::hooks::{
-    AssumeUtxoHookExecutor, FullIbdHookExecutor, HookExecutor, HookMode,
+    AssumeUtxoHookExecutor, DatadirHookExecutor, FullIbdHookExecutor, HookExecutor, HookMode,
+    LoadBlockHookExecutor, PrunedHookExecutor, ReindexHookExecutor, UpgradeHookExecutor,
 };
+use crate::benchmarks::prune::PruneConfig;
+use crate::command::CommandExecutor;
 
 /// Represents the different hook script stages
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum HookStage {
     Setup,
     Prepare,
@@ -28,6 +35,19 @@ pub struct HookArgs {
     pub out_dir: PathBuf,
     /// Path to snapshot file
     pub snapshot_path: PathBuf,
+    /// Pre-synced datadir each run starts from, in datadir mode
+    pub source_datadir: Option<PathBuf>,
+    /// Commit whose binary creates the datadir each run upgrades from, in
+    /// upgrade mode
+    pub upgrade_from: Option<String>,
+    /// Height the upgrade_from binary syncs to
+    pub upgrade_height: Option<u64>,
+    /// Datadir whose block files are staged in reindex modes
+    pub block_source: Option<PathBuf>,
+    /// Block files, or directories of them, imported in load block mode
+    pub load_blocks: Vec<PathBuf>,
+    /// Prune target and preparation, in pruned mode
+    pub prune: Option<PruneConfig>,
     /// Temporary data directory for the benchmarked process
     pub tmp_data_dir: PathBuf,
     /// Current iteration number
@@ -38,9 +58,123 @@ pub struct HookArgs {
     pub params_dir: String,
 }
 
+impl HookArgs {
+    /// Directory the output of this stage is written to: the iteration
+    /// directory for per-run stages, otherwise the parameter directory
+    pub fn stage_dir(&self, stage: HookStage) -> PathBuf {
+        let dir = self.out_dir.join(&self.commit).join(&self.params_dir);
+        match stage {
+            HookStage::Prepare | HookStage::Conclude => dir.join(self.iteration.to_string()),
+            HookStage::Setup | HookStage::Cleanup => dir,
+        }
+    }
+
+    /// Environment variables describing the run, passed to script hooks
+    pub fn env_vars(&self, stage: HookStage) -> HashMap<String, String> {
+        HashMap::from([
+            ("BENCHKIT_STAGE".to_string(), stage.name().to_string()),
+            ("BENCHKIT_COMMIT".to_string(), self.commit.clone()),
+            ("BENCHKIT_ITERATION".to_string(), self.iteration.to_string()),
+            ("BENCHKIT_PARAMS".to_string(), self.params_dir.clone()),
+            ("BENCHKIT_NETWORK".to_string(), self.network.clone()),
+            (
+                "BENCHKIT_BINARY".to_string(),
+                self.binary.replace("{commit}", &self.commit),
+            ),
+            (
+                "BENCHKIT_DATADIR".to_string(),
+                self.tmp_data_dir.display().to_string(),
+            ),
+            (
+                "BENCHKIT_OUT_DIR".to_string(),
+                self.out_dir.display().to_string(),
+            ),
+            (
+                "BENCHKIT_RUN_DIR".to_string(),
+                self.stage_dir(stage).display().to_string(),
+            ),
+        ])
+    }
+}
+
+impl HookStage {
+    /// Name of the stage as used in config and file names
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookStage::Setup => "setup",
+            HookStage::Prepare => "prepare",
+            HookStage::Conclude => "conclude",
+            HookStage::Cleanup => "cleanup",
+        }
+    }
+}
+
+/// Shell commands run before and after the built-in actions of a stage
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StageScripts {
+    #[serde(default)]
+    pub before: Vec<String>,
+    #[serde(default)]
+    pub after: Vec<String>,
+}
+
+/// User-defined shell commands for each hook stage
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScriptHooks {
+    #[serde(default)]
+    pub setup: StageScripts,
+    #[serde(default)]
+    pub prepare: StageScripts,
+    #[serde(default)]
+    pub conclude: StageScripts,
+    #[serde(default)]
+    pub cleanup: StageScripts,
+}
+
+impl ScriptHooks {
+    fn stage(&self, stage: HookStage) -> &StageScripts {
+        match stage {
+            HookStage::Setup => &self.setup,
+            HookStage::Prepare => &self.prepare,
+            HookStage::Conclude => &self.conclude,
+            HookStage::Cleanup => &self.cleanup,
+        }
+    }
+}
+
+/// Whether a script ran before or after the built-in actions of its stage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookPosition {
+    /// A script run before the built-in actions
+    Before,
+    /// The built-in actions of the hook mode
+    Builtin,
+    /// A script run after the built-in actions
+    After,
+}
+
+/// Timing of one step of a hook stage
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HookRecord {
+    pub stage: HookStage,
+    pub position: HookPosition,
+    /// Shell command, or `None` for the built-in actions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// Time taken in milliseconds
+    pub duration_ms: f64,
+    /// File the output of the command was written to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log: Option<PathBuf>,
+}
+
 /// HookRunner manages the lifecycle hooks for benchmarks
 pub struct HookRunner {
     executor: Box<dyn HookExecutor>,
+    scripts: ScriptHooks,
 }
 
 impl Default for HookRunner {
@@ -60,20 +194,195 @@ impl HookRunner {
         let executor: Box<dyn HookExecutor> = match mode {
             HookMode::AssumeUtxo => Box::new(AssumeUtxoHookExecutor::new()),
             HookMode::FullIbd => Box::new(FullIbdHookExecutor::new()),
+            HookMode::Datadir => Box::new(DatadirHookExecutor::new()),
+            HookMode::Upgrade => Box::new(UpgradeHookExecutor::new()),
+            HookMode::Reindex => Box::new(ReindexHookExecutor::new(false)),
+            HookMode::ReindexChainstate => Box::new(ReindexHookExecutor::new(true)),
+            HookMode::LoadBlock => Box::new(LoadBlockHookExecutor::new()),
+            HookMode::Pruned => Box::new(PrunedHookExecutor::new()),
         };
 
-        Self { executor }
+        Self {
+            executor,
+            scripts: ScriptHooks::default(),
+        }
+    }
+
+    /// Run these shell commands around the built-in actions of each stage
+    pub fn scripts(mut self, scripts: ScriptHooks) -> Self {
+        self.scripts = scripts;
+        self
     }
 
-    /// Run a hook for the given stage
-    pub fn run_hook(&self, stage: HookStage, args: &HookArgs) -> Result<()> {
+    /// Arguments the mode appends to the benchmarked command
+    pub fn command_args(&self, args: &HookArgs) -> Result<Vec<String>> {
+        self.executor.command_args(args)
+    }
+
+    /// Run a hook for the given stage: the `before` scripts, the built-in
+    /// actions of the mode, then the `after` scripts
+    pub fn run_hook(&self, stage: HookStage, args: &HookArgs) -> Result<Vec<HookRecord>> {
         info!("Running {stage:?} hook");
+        let scripts = self.scripts.stage(stage);
+        let mut records = Vec::new();
+
+        for (index, command) in scripts.before.iter().enumerate() {
+            records.push(Self::run_script(
+                stage,
+                HookPosition::Before,
+                index,
+                command,
+                args,
+            )?);
+        }
 
+        let start = Instant::now();
         match stage {
             HookStage::Setup => self.executor.setup(args),
             HookStage::Prepare => self.executor.prepare(args),
             HookStage::Conclude => self.executor.conclude(args),
             HookStage::Cleanup => self.executor.cleanup(args),
+        }?;
+        records.push(HookRecord {
+            stage,
+            position: HookPosition::Builtin,
+            command: None,
+            duration_ms: start.elapsed().as_secs_f64() * 1000.0,
+            log: None,
+        });
+
+        for (index, command) in scripts.after.iter().enumerate() {
+            records.push(Self::run_script(
+                stage,
+                HookPosition::After,
+                index,
+                command,
+                args,
+            )?);
+        }
+
+        Ok(records)
+    }
+
+    /// Run a script hook with the run described in its environment, writing
+    /// its output to a log file. A failing script fails the hook.
+    fn run_script(
+        stage: HookStage,
+        position: HookPosition,
+        index: usize,
+        command: &str,
+        args: &HookArgs,
+    ) -> Result<HookRecord> {
+        let log_dir = args.stage_dir(stage).join("hooks");
+        std::fs::create_dir_all(&log_dir)
+            .with_context(|| format!("Failed to create {}", log_dir.display()))?;
+        let position_name = match position {
+            HookPosition::Before => "before",
+            HookPosition::Builtin => "builtin",
+            HookPosition::After => "after",
+        };
+        let log = log_dir.join(format!("{}_{position_name}_{index}.log", stage.name()));
+
+        debug!("Running {} hook script: {command}", stage.name());
+        let start = Instant::now();
+        let output = CommandExecutor::builder()
+            .env_vars(args.env_vars(stage))
+            .capture_output(true)
+            .allow_failure(true)
+            .build()?
+            .execute_shell(command)?;
+        let duration_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+        let mut contents = output.stdout;
+        contents.extend_from_slice(&output.stderr);
+        std::fs::write(&log, contents)
+            .with_context(|| format!("Failed to write {}", log.display()))?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "{} hook script failed with status {}: {command} (output in {})",
+                stage.name(),
+                output.status.code().unwrap_or(-1),
+                log.display()
+            );
         }
+
+        Ok(HookRecord {
+            stage,
+            position,
+            command: Some(command.to_string()),
+            duration_ms,
+            log: Some(log),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_script_hooks() {
+        let dir = tempfile::tempdir().unwrap();
+        let args = HookArgs {
+            binary: "/bin/bitcoind-{commit}".to_string(),
+            connect_address: String::new(),
+            network: "signet".to_string(),
+            out_dir: dir.path().join("out"),
+            snapshot_path: PathBuf::new(),
+            source_datadir: None,
+            upgrade_from: None,
+            upgrade_height: None,
+            block_source: None,
+            load_blocks: Vec::new(),
+            prune: None,
+            tmp_data_dir: dir.path().join("data"),
+            iteration: 2,
+            commit: "abc".to_string(),
+            params_dir: "default".to_string(),
+        };
+        let runner = HookRunner::with_mode(HookMode::FullIbd).scripts(ScriptHooks {
+            prepare: StageScripts {
+                before: vec![
+                    "echo $BENCHKIT_STAGE $BENCHKIT_BINARY $BENCHKIT_ITERATION".to_string()
+                ],
+                after: vec!["ls $BENCHKIT_DATADIR".to_string()],
+            },
+            ..Default::default()
+        });
+
+        let records = runner.run_hook(HookStage::Prepare, &args).unwrap();
+        let positions: Vec<HookPosition> = records.iter().map(|r| r.position).collect();
+        assert_eq!(
+            positions,
+            vec![
+                HookPosition::Before,
+                HookPosition::Builtin,
+                HookPosition::After
+            ]
+        );
+        // The built-in prepare created the datadir the after script lists
+        let log = std::fs::read_to_string(records[0].log.as_ref().unwrap()).unwrap();
+        assert_eq!(log, "prepare /bin/bitcoind-abc 2\n");
+        assert_eq!(
+            records[2].log.as_deref(),
+            Some(
+                dir.path()
+                    .join("out/abc/default/2/hooks/prepare_after_0.log")
+                    .as_path()
+            )
+        );
+
+        let runner = HookRunner::with_mode(HookMode::FullIbd).scripts(ScriptHooks {
+            setup: StageScripts {
+                before: vec!["echo oops >&2; exit 3".to_string()],
+                after: Vec::new(),
+            },
+            ..Default::default()
+        });
+        let err = runner.run_hook(HookStage::Setup, &args).unwrap_err();
+        assert!(err.to_string().contains("status 3"));
+        let log = dir.path().join("out/abc/default/hooks/setup_before_0.log");
+        assert_eq!(std::fs::read_to_string(log).unwrap(), "oops\n");
     }
 }
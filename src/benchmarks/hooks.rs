@@ -1,9 +1,12 @@
 use anyhow::{Context, Result};
 use log::{debug, info};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
+use crate::benchmarks::datadir::{fingerprint, restore_datadir};
 use crate::benchmarks::hook_runner::HookArgs;
+use crate::benchmarks::utils::{build_bitcoind_base_args, get_network_datadir};
 use crate::command::{CommandContext, CommandExecutor};
 
 /// Different modes for benchmark hook execution
@@ -14,6 +17,22 @@ pub enum HookMode {
     AssumeUtxo,
     /// Full IBD mode - no snapshot, full initial block download
     FullIbd,
+    /// Datadir mode - each run starts from a copy of a pre-synced datadir
+    Datadir,
+    /// Upgrade mode - each run starts from a datadir created by an older binary
+    Upgrade,
+    /// Reindex mode - each run rebuilds the block index and chainstate from
+    /// staged block files
+    Reindex,
+    /// Reindex chainstate mode - each run rebuilds the chainstate from staged
+    /// block files
+    ReindexChainstate,
+    /// Load block mode - each run imports blocks from local files with
+    /// `-loadblock` instead of downloading them
+    LoadBlock,
+    /// Pruned mode - each run is prepared as in AssumeUTXO or full IBD mode,
+    /// and runs with `-prune`
+    Pruned,
 }
 
 impl HookMode {
@@ -22,6 +41,14 @@ impl HookMode {
         match s.to_lowercase().as_str() {
             "assumeutxo" => Ok(HookMode::AssumeUtxo),
             "full_ibd" | "fullibd" | "full-ibd" => Ok(HookMode::FullIbd),
+            "datadir" => Ok(HookMode::Datadir),
+            "upgrade" => Ok(HookMode::Upgrade),
+            "reindex" => Ok(HookMode::Reindex),
+            "reindex_chainstate" | "reindexchainstate" | "reindex-chainstate" => {
+                Ok(HookMode::ReindexChainstate)
+            }
+            "loadblock" | "load_block" | "load-block" => Ok(HookMode::LoadBlock),
+            "pruned" | "prune" => Ok(HookMode::Pruned),
             _ => anyhow::bail!("Unknown hook mode: {}", s),
         }
     }
@@ -33,6 +60,11 @@ pub trait HookExecutor {
     fn prepare(&self, args: &HookArgs) -> Result<()>;
     fn conclude(&self, args: &HookArgs) -> Result<()>;
     fn cleanup(&self, args: &HookArgs) -> Result<()>;
+
+    /// Arguments appended to the benchmarked command in this mode
+    fn command_args(&self, _args: &HookArgs) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
 }
 
 /// Base hook executor with common functionality
@@ -79,20 +111,8 @@ impl BaseHookExecutor {
         self.clear_directory(path)?;
         Ok(())
     }
-}
-
-/// AssumeUTXO hook executor - uses snapshot syncing
-pub struct AssumeUtxoHookExecutor {
-    base: BaseHookExecutor,
-}
-
-impl AssumeUtxoHookExecutor {
-    pub fn new() -> Self {
-        Self {
-            base: BaseHookExecutor,
-        }
-    }
 
+    /// Execute a Bitcoin Core command, failing if it exits unsuccessfully
     fn execute_bitcoin_command(&self, binary: &str, args: &[&str]) -> Result<()> {
         let command_desc = format!("{} {}", binary, args.join(" "));
         let context = CommandContext {
@@ -117,6 +137,60 @@ impl AssumeUtxoHookExecutor {
         Ok(())
     }
 
+    /// Move the run's debug.log into its output directory
+    fn save_debug_log(&self, args: &HookArgs) -> Result<()> {
+        // Create output directory structure
+        let output_path = args
+            .out_dir
+            .join(&args.commit)
+            .join(&args.params_dir)
+            .join(args.iteration.to_string());
+
+        info!("Moving debug.log to {}", output_path.display());
+        self.create_directory(&output_path)?;
+
+        // Determine debug.log source path based on network
+        let debug_log_source =
+            get_network_datadir(&args.tmp_data_dir, &args.network).join("debug.log");
+
+        let debug_log_dest = output_path.join("debug.log");
+
+        // Move debug.log
+        if debug_log_source.exists() {
+            fs::rename(&debug_log_source, &debug_log_dest)
+                .or_else(|_| -> Result<()> {
+                    // If rename fails (e.g., cross-filesystem), fall back to copy and delete
+                    fs::copy(&debug_log_source, &debug_log_dest)?;
+                    fs::remove_file(&debug_log_source)?;
+                    Ok(())
+                })
+                .with_context(|| {
+                    format!(
+                        "Failed to move debug.log from {} to {}",
+                        debug_log_source.display(),
+                        debug_log_dest.display()
+                    )
+                })?;
+        } else {
+            debug!("debug.log not found at {}", debug_log_source.display());
+        }
+
+        Ok(())
+    }
+}
+
+/// AssumeUTXO hook executor - uses snapshot syncing
+pub struct AssumeUtxoHookExecutor {
+    base: BaseHookExecutor,
+}
+
+impl AssumeUtxoHookExecutor {
+    pub fn new() -> Self {
+        Self {
+            base: BaseHookExecutor,
+        }
+    }
+
     /// Execute a Bitcoin Core command that may fail (like loadutxosnapshot)
     fn execute_bitcoin_command_allow_failure(&self, binary: &str, args: &[&str]) -> Result<()> {
         let command_desc = format!("{} {}", binary, args.join(" "));
@@ -168,7 +242,8 @@ impl HookExecutor for AssumeUtxoHookExecutor {
 
         // Convert to &str references for execute_bitcoin_command
         let sync_args: Vec<&str> = base_args.iter().map(|s| s.as_str()).collect();
-        self.execute_bitcoin_command(&binary_path, &sync_args)?;
+        self.base
+            .execute_bitcoin_command(&binary_path, &sync_args)?;
 
         // Load snapshot
         info!("Loading snapshot");
@@ -196,44 +271,7 @@ impl HookExecutor for AssumeUtxoHookExecutor {
     fn conclude(&self, args: &HookArgs) -> Result<()> {
         info!("Running AssumeUTXO conclude hook");
 
-        // Create output directory structure
-        let output_path = args
-            .out_dir
-            .join(&args.commit)
-            .join(&args.params_dir)
-            .join(args.iteration.to_string());
-
-        info!("Moving debug.log to {}", output_path.display());
-        self.base.create_directory(&output_path)?;
-
-        // Determine debug.log source path based on network
-        let debug_log_source = if args.network == "main" {
-            args.tmp_data_dir.join("debug.log")
-        } else {
-            args.tmp_data_dir.join(&args.network).join("debug.log")
-        };
-
-        let debug_log_dest = output_path.join("debug.log");
-
-        // Move debug.log
-        if debug_log_source.exists() {
-            fs::rename(&debug_log_source, &debug_log_dest)
-                .or_else(|_| -> Result<()> {
-                    // If rename fails (e.g., cross-filesystem), fall back to copy and delete
-                    fs::copy(&debug_log_source, &debug_log_dest)?;
-                    fs::remove_file(&debug_log_source)?;
-                    Ok(())
-                })
-                .with_context(|| {
-                    format!(
-                        "Failed to move debug.log from {} to {}",
-                        debug_log_source.display(),
-                        debug_log_dest.display()
-                    )
-                })?;
-        } else {
-            debug!("debug.log not found at {}", debug_log_source.display());
-        }
+        self.base.save_debug_log(args)?;
 
         // Clean datadir contents
         self.base.clear_directory(&args.tmp_data_dir)?;
@@ -291,45 +329,441 @@ impl HookExecutor for FullIbdHookExecutor {
     fn conclude(&self, args: &HookArgs) -> Result<()> {
         info!("Running Full IBD conclude hook");
 
-        // Create output directory structure
-        let output_path = args
-            .out_dir
-            .join(&args.commit)
-            .join(&args.params_dir)
-            .join(args.iteration.to_string());
+        self.base.save_debug_log(args)?;
 
-        info!("Moving debug.log to {}", output_path.display());
-        self.base.create_directory(&output_path)?;
+        // Clean datadir contents
+        self.base.clear_directory(&args.tmp_data_dir)?;
 
-        // Determine debug.log source path based on network
-        let debug_log_source = if args.network == "main" {
-            args.tmp_data_dir.join("debug.log")
+        Ok(())
+    }
+
+    fn cleanup(&self, args: &HookArgs) -> Result<()> {
+        info!("Running Full IBD cleanup hook");
+
+        // Final cleanup of datadir
+        self.base.clear_directory(&args.tmp_data_dir)?;
+
+        Ok(())
+    }
+}
+
+/// Datadir hook executor - restores a pre-synced datadir before each run
+pub struct DatadirHookExecutor {
+    base: BaseHookExecutor,
+    /// Fingerprint of the source datadir taken at the first setup, so every
+    /// parameter set and commit starts from the same state
+    fingerprint: Mutex<Option<u64>>,
+}
+
+impl DatadirHookExecutor {
+    pub fn new() -> Self {
+        Self {
+            base: BaseHookExecutor,
+            fingerprint: Mutex::new(None),
+        }
+    }
+
+    fn source(args: &HookArgs) -> Result<&PathBuf> {
+        args.source_datadir
+            .as_ref()
+            .context("Datadir mode requires a source_datadir")
+    }
+
+    /// Check the source datadir has not changed since the first setup
+    fn verify_source(&self, source: &Path) -> Result<()> {
+        let expected = *self.fingerprint.lock().unwrap();
+        if expected.is_some() && expected != Some(fingerprint(source)?) {
+            anyhow::bail!(
+                "Source datadir {} was modified during the benchmark",
+                source.display()
+            );
+        }
+        Ok(())
+    }
+}
+
+impl Default for DatadirHookExecutor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HookExecutor for DatadirHookExecutor {
+    fn setup(&self, args: &HookArgs) -> Result<()> {
+        info!("Running datadir setup hook");
+        let source = Self::source(args)?;
+        if !source.is_dir() {
+            anyhow::bail!("Source datadir {} does not exist", source.display());
+        }
+        // Fingerprinted once, so changes between parameter sets, including
+        // commits, are caught
+        if self.fingerprint.lock().unwrap().is_some() {
+            self.verify_source(source)?;
         } else {
-            args.tmp_data_dir.join(&args.network).join("debug.log")
-        };
+            *self.fingerprint.lock().unwrap() = Some(fingerprint(source)?);
+        }
 
-        let debug_log_dest = output_path.join("debug.log");
+        self.base.create_directory(&args.tmp_data_dir)?;
+        self.base.clear_directory(&args.tmp_data_dir)?;
 
-        // Move debug.log
-        if debug_log_source.exists() {
-            fs::rename(&debug_log_source, &debug_log_dest)
-                .or_else(|_| -> Result<()> {
-                    // If rename fails (e.g., cross-filesystem), fall back to copy and delete
-                    fs::copy(&debug_log_source, &debug_log_dest)?;
-                    fs::remove_file(&debug_log_source)?;
-                    Ok(())
-                })
-                .with_context(|| {
-                    format!(
-                        "Failed to move debug.log from {} to {}",
-                        debug_log_source.display(),
-                        debug_log_dest.display()
-                    )
-                })?;
+        Ok(())
+    }
+
+    fn prepare(&self, args: &HookArgs) -> Result<()> {
+        info!("Running datadir prepare hook");
+        let source = Self::source(args)?;
+        self.verify_source(source)?;
+
+        self.base.clear_and_recreate_directory(&args.tmp_data_dir)?;
+        restore_datadir(source, &args.tmp_data_dir)?;
+
+        Ok(())
+    }
+
+    fn conclude(&self, args: &HookArgs) -> Result<()> {
+        info!("Running datadir conclude hook");
+
+        self.base.save_debug_log(args)?;
+
+        // Clean datadir contents
+        self.base.clear_directory(&args.tmp_data_dir)?;
+
+        Ok(())
+    }
+
+    fn cleanup(&self, args: &HookArgs) -> Result<()> {
+        info!("Running datadir cleanup hook");
+        self.verify_source(Self::source(args)?)?;
+
+        // Final cleanup of datadir
+        self.base.clear_directory(&args.tmp_data_dir)?;
+
+        Ok(())
+    }
+}
+
+/// Upgrade hook executor - restores a datadir created by the `upgrade_from`
+/// binary before each run, so the benchmark measures the first startup of the
+/// benchmarked commit on it. The datadir is either `source_datadir` or synced
+/// to `upgrade_height` once, at the first setup.
+pub struct UpgradeHookExecutor {
+    datadir: DatadirHookExecutor,
+    /// Datadir synced by the upgrade_from binary, when no source is configured
+    synced: Mutex<Option<PathBuf>>,
+}
+
+impl UpgradeHookExecutor {
+    pub fn new() -> Self {
+        Self {
+            datadir: DatadirHookExecutor::new(),
+            synced: Mutex::new(None),
+        }
+    }
+
+    /// Sync a datadir with the upgrade_from binary up to the upgrade height
+    fn sync(&self, args: &HookArgs) -> Result<PathBuf> {
+        let from = args
+            .upgrade_from
+            .as_ref()
+            .context("Upgrade mode requires upgrade_from")?;
+        let height = args
+            .upgrade_height
+            .context("Upgrade mode requires upgrade_height or source_datadir")?;
+        let mut name = args.tmp_data_dir.as_os_str().to_owned();
+        name.push(format!("-upgrade-{from}"));
+        let datadir = PathBuf::from(name);
+
+        info!(
+            "Syncing {} to height {height} with {from}",
+            datadir.display()
+        );
+        self.datadir.base.clear_and_recreate_directory(&datadir)?;
+        let mut sync_args =
+            build_bitcoind_base_args(&args.network, &datadir, &args.connect_address);
+        sync_args.extend_from_slice(&[
+            "-daemon=0".to_string(),
+            format!("-stopatheight={height}"),
+            "-printtoconsole=0".to_string(),
+        ]);
+        let sync_args: Vec<&str> = sync_args.iter().map(|s| s.as_str()).collect();
+        self.datadir
+            .base
+            .execute_bitcoin_command(&args.binary.replace("{commit}", from), &sync_args)?;
+
+        Ok(datadir)
+    }
+
+    /// The hook arguments with the datadir to upgrade from as the source
+    fn source_args(&self, args: &HookArgs) -> HookArgs {
+        let mut args = args.clone();
+        if let Some(synced) = self.synced.lock().unwrap().as_ref() {
+            args.source_datadir = Some(synced.clone());
+        }
+        args
+    }
+}
+
+impl Default for UpgradeHookExecutor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for UpgradeHookExecutor {
+    fn drop(&mut self) {
+        if let Some(synced) = self.synced.lock().unwrap().take() {
+            debug!("Removing synced datadir {}", synced.display());
+            if let Err(e) = fs::remove_dir_all(&synced) {
+                debug!("Failed to remove {}: {e}", synced.display());
+            }
+        }
+    }
+}
+
+impl HookExecutor for UpgradeHookExecutor {
+    fn setup(&self, args: &HookArgs) -> Result<()> {
+        info!("Running upgrade setup hook");
+        // Synced once and shared by every parameter set of the benchmark
+        if args.source_datadir.is_none() && self.synced.lock().unwrap().is_none() {
+            let synced = self.sync(args)?;
+            *self.synced.lock().unwrap() = Some(synced);
+        }
+        self.datadir.setup(&self.source_args(args))
+    }
+
+    fn prepare(&self, args: &HookArgs) -> Result<()> {
+        self.datadir.prepare(&self.source_args(args))
+    }
+
+    fn conclude(&self, args: &HookArgs) -> Result<()> {
+        self.datadir.conclude(&self.source_args(args))
+    }
+
+    fn cleanup(&self, args: &HookArgs) -> Result<()> {
+        self.datadir.cleanup(&self.source_args(args))
+    }
+}
+
+/// Reindex hook executor - stages the block files of `block_source` into the
+/// datadir at setup, and before each run removes what the reindex rebuilds
+pub struct ReindexHookExecutor {
+    base: BaseHookExecutor,
+    /// Only rebuild the chainstate, keeping the block index
+    chainstate_only: bool,
+    /// Data directory the blocks were staged into, when they have been
+    staged: Mutex<Option<PathBuf>>,
+}
+
+impl ReindexHookExecutor {
+    pub fn new(chainstate_only: bool) -> Self {
+        Self {
+            base: BaseHookExecutor,
+            chainstate_only,
+            staged: Mutex::new(None),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        if self.chainstate_only {
+            "reindex-chainstate"
         } else {
-            debug!("debug.log not found at {}", debug_log_source.display());
+            "reindex"
+        }
+    }
+
+    /// The blocks directory of the block source
+    fn source_blocks(args: &HookArgs) -> Result<PathBuf> {
+        let source = args
+            .block_source
+            .as_ref()
+            .context("Reindex modes require a block_source")?;
+        Ok(get_network_datadir(source, &args.network).join("blocks"))
+    }
+
+    /// Remove everything in the network datadir but the staged blocks
+    fn remove_all_but_blocks(args: &HookArgs) -> Result<()> {
+        let network_datadir = get_network_datadir(&args.tmp_data_dir, &args.network);
+        for entry in fs::read_dir(&network_datadir)
+            .with_context(|| format!("Failed to read {}", network_datadir.display()))?
+        {
+            let path = entry?.path();
+            if path.file_name().is_some_and(|name| name == "blocks") {
+                continue;
+            }
+            if path.is_dir() {
+                fs::remove_dir_all(&path)
+            } else {
+                fs::remove_file(&path)
+            }
+            .with_context(|| format!("Failed to remove {}", path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ReindexHookExecutor {
+    fn drop(&mut self) {
+        if let Some(staged) = self.staged.lock().unwrap().take() {
+            debug!("Removing staged blocks from {}", staged.display());
+            if let Err(e) = self.base.clear_directory(&staged) {
+                debug!("Failed to clear {}: {e}", staged.display());
+            }
+        }
+    }
+}
+
+impl HookExecutor for ReindexHookExecutor {
+    fn setup(&self, args: &HookArgs) -> Result<()> {
+        info!("Running {} setup hook", self.name());
+        // Staged once for the whole benchmark. Prepare resets everything a
+        // run rebuilds, so the blocks are reused across parameter sets just
+        // as they are across runs.
+        let mut staged = self.staged.lock().unwrap();
+        let blocks = get_network_datadir(&args.tmp_data_dir, &args.network).join("blocks");
+        if staged.as_ref() == Some(&args.tmp_data_dir) && blocks.is_dir() {
+            debug!("Reusing blocks staged in {}", blocks.display());
+            return Ok(());
+        }
+
+        let source = Self::source_blocks(args)?;
+        if !source.is_dir() {
+            anyhow::bail!("Block source {} does not exist", source.display());
+        }
+        self.base.clear_and_recreate_directory(&args.tmp_data_dir)?;
+        restore_datadir(&source, &blocks)?;
+        *staged = Some(args.tmp_data_dir.clone());
+
+        Ok(())
+    }
+
+    fn prepare(&self, args: &HookArgs) -> Result<()> {
+        info!("Running {} prepare hook", self.name());
+        let network_datadir = get_network_datadir(&args.tmp_data_dir, &args.network);
+
+        // Everything but the staged blocks is rebuilt
+        Self::remove_all_but_blocks(args)?;
+
+        // A reindex rebuilds the block index, while reindexing the chainstate
+        // updates it, so it is restored to its staged state
+        let index = network_datadir.join("blocks").join("index");
+        if index.exists() {
+            fs::remove_dir_all(&index)
+                .with_context(|| format!("Failed to remove {}", index.display()))?;
+        }
+        if self.chainstate_only {
+            restore_datadir(&Self::source_blocks(args)?.join("index"), &index)?;
+        }
+
+        Ok(())
+    }
+
+    fn conclude(&self, args: &HookArgs) -> Result<()> {
+        info!("Running {} conclude hook", self.name());
+
+        // The staged blocks are kept for the next run
+        self.base.save_debug_log(args)?;
+
+        Ok(())
+    }
+
+    fn cleanup(&self, args: &HookArgs) -> Result<()> {
+        info!("Running {} cleanup hook", self.name());
+
+        // The staged blocks are kept for the next parameter set, and removed
+        // when the benchmark is done
+        Self::remove_all_but_blocks(args)?;
+
+        Ok(())
+    }
+
+    fn command_args(&self, args: &HookArgs) -> Result<Vec<String>> {
+        let mut command_args = vec![format!("-{}", self.name())];
+        // Blocks only come from disk unless a peer is configured
+        if args.connect_address.is_empty() {
+            command_args.push("-connect=0".to_string());
+        }
+        Ok(command_args)
+    }
+}
+
+/// Load block hook executor - starts each run from an empty datadir, with the
+/// benchmarked command importing the configured block files
+pub struct LoadBlockHookExecutor {
+    base: BaseHookExecutor,
+}
+
+impl LoadBlockHookExecutor {
+    pub fn new() -> Self {
+        Self {
+            base: BaseHookExecutor,
+        }
+    }
+
+    /// The files to import, in order. Directories are expanded to the
+    /// `blk*.dat` files they contain.
+    fn block_files(args: &HookArgs) -> Result<Vec<PathBuf>> {
+        if args.load_blocks.is_empty() {
+            anyhow::bail!("Load block mode requires load_blocks");
         }
 
+        let mut files = Vec::new();
+        for path in &args.load_blocks {
+            if path.is_dir() {
+                let mut dir_files = Vec::new();
+                for entry in fs::read_dir(path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?
+                {
+                    let file = entry?.path();
+                    let name = file.file_name().unwrap_or_default().to_string_lossy();
+                    if name.starts_with("blk") && name.ends_with(".dat") {
+                        dir_files.push(file);
+                    }
+                }
+                if dir_files.is_empty() {
+                    anyhow::bail!("No blk*.dat files found in {}", path.display());
+                }
+                dir_files.sort();
+                files.extend(dir_files);
+            } else if path.is_file() {
+                files.push(path.clone());
+            } else {
+                anyhow::bail!("Block file {} does not exist", path.display());
+            }
+        }
+        Ok(files)
+    }
+}
+
+impl Default for LoadBlockHookExecutor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HookExecutor for LoadBlockHookExecutor {
+    fn setup(&self, args: &HookArgs) -> Result<()> {
+        info!("Running load block setup hook");
+        Self::block_files(args)?;
+        self.base.create_directory(&args.tmp_data_dir)?;
+        self.base.clear_directory(&args.tmp_data_dir)?;
+
+        Ok(())
+    }
+
+    fn prepare(&self, args: &HookArgs) -> Result<()> {
+        info!("Running load block prepare hook");
+
+        // Create datadir and clear contents
+        self.base.clear_and_recreate_directory(&args.tmp_data_dir)?;
+
+        Ok(())
+    }
+
+    fn conclude(&self, args: &HookArgs) -> Result<()> {
+        info!("Running load block conclude hook");
+
+        self.base.save_debug_log(args)?;
+
         // Clean datadir contents
         self.base.clear_directory(&args.tmp_data_dir)?;
 
@@ -337,13 +771,86 @@ impl HookExecutor for FullIbdHookExecutor {
     }
 
     fn cleanup(&self, args: &HookArgs) -> Result<()> {
-        info!("Running Full IBD cleanup hook");
+        info!("Running load block cleanup hook");
 
         // Final cleanup of datadir
         self.base.clear_directory(&args.tmp_data_dir)?;
 
         Ok(())
     }
+
+    fn command_args(&self, args: &HookArgs) -> Result<Vec<String>> {
+        let mut command_args: Vec<String> = Self::block_files(args)?
+            .iter()
+            .map(|file| format!("-loadblock={}", file.display()))
+            .collect();
+        // Blocks only come from the files unless a peer is configured
+        if args.connect_address.is_empty() {
+            command_args.push("-connect=0".to_string());
+        }
+        Ok(command_args)
+    }
+}
+
+/// Pruned hook executor - prepares each run as the `prune.prepare` mode does,
+/// with the benchmarked command pruning to `prune.target`
+pub struct PrunedHookExecutor {
+    assumeutxo: AssumeUtxoHookExecutor,
+    full_ibd: FullIbdHookExecutor,
+}
+
+impl PrunedHookExecutor {
+    pub fn new() -> Self {
+        Self {
+            assumeutxo: AssumeUtxoHookExecutor::new(),
+            full_ibd: FullIbdHookExecutor::new(),
+        }
+    }
+
+    /// The executor of the mode each run is prepared with
+    fn prepare_executor(&self, args: &HookArgs) -> Result<&dyn HookExecutor> {
+        let prune = args
+            .prune
+            .as_ref()
+            .context("Pruned mode requires prune to be configured")?;
+        Ok(match prune.prepare_mode()? {
+            HookMode::FullIbd => &self.full_ibd,
+            _ => &self.assumeutxo,
+        })
+    }
+}
+
+impl Default for PrunedHookExecutor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HookExecutor for PrunedHookExecutor {
+    fn setup(&self, args: &HookArgs) -> Result<()> {
+        self.prepare_executor(args)?.setup(args)
+    }
+
+    fn prepare(&self, args: &HookArgs) -> Result<()> {
+        self.prepare_executor(args)?.prepare(args)
+    }
+
+    fn conclude(&self, args: &HookArgs) -> Result<()> {
+        self.prepare_executor(args)?.conclude(args)
+    }
+
+    fn cleanup(&self, args: &HookArgs) -> Result<()> {
+        self.prepare_executor(args)?.cleanup(args)
+    }
+
+    fn command_args(&self, args: &HookArgs) -> Result<Vec<String>> {
+        let prune = args
+            .prune
+            .as_ref()
+            .context("Pruned mode requires prune to be configured")?;
+        // Placeholders in the target are filled in with the rest of the command
+        Ok(vec![format!("-prune={}", prune.target)])
+    }
 }
 
 #[cfg(test)]
@@ -386,4 +893,146 @@ mod tests {
         // Directory itself should still exist
         assert!(test_path.exists());
     }
+
+    #[test]
+    fn test_datadir_source_verified_across_setups() {
+        let temp_dir = tempdir().unwrap();
+        let source = temp_dir.path().join("source");
+        fs::create_dir_all(source.join("signet/chainstate")).unwrap();
+        fs::write(source.join("signet/chainstate/000001.ldb"), "utxos").unwrap();
+        let args = HookArgs {
+            binary: String::new(),
+            connect_address: String::new(),
+            network: "signet".to_string(),
+            out_dir: temp_dir.path().join("out"),
+            snapshot_path: PathBuf::new(),
+            source_datadir: Some(source.clone()),
+            upgrade_from: None,
+            upgrade_height: None,
+            block_source: None,
+            load_blocks: Vec::new(),
+            prune: None,
+            tmp_data_dir: temp_dir.path().join("data"),
+            iteration: 0,
+            commit: "a".to_string(),
+            params_dir: "default".to_string(),
+        };
+        let executor = DatadirHookExecutor::new();
+        executor.setup(&args).unwrap();
+        executor.prepare(&args).unwrap();
+
+        // The setup for the next commit finds the source changed
+        fs::write(source.join("signet/chainstate/000002.ldb"), "more").unwrap();
+        let next = HookArgs {
+            commit: "b".to_string(),
+            ..args
+        };
+        let err = executor.setup(&next).unwrap_err();
+        assert!(err.to_string().contains("was modified"));
+    }
+
+    #[test]
+    fn test_reindex_chainstate_prepare() {
+        let temp_dir = tempdir().unwrap();
+        let source = temp_dir.path().join("source");
+        fs::create_dir_all(source.join("signet/blocks/index")).unwrap();
+        fs::write(source.join("signet/blocks/blk00000.dat"), "blocks").unwrap();
+        fs::write(source.join("signet/blocks/index/000001.ldb"), "index").unwrap();
+        let args = HookArgs {
+            binary: String::new(),
+            connect_address: String::new(),
+            network: "signet".to_string(),
+            out_dir: temp_dir.path().join("out"),
+            snapshot_path: PathBuf::new(),
+            source_datadir: None,
+            upgrade_from: None,
+            upgrade_height: None,
+            block_source: Some(source),
+            load_blocks: Vec::new(),
+            prune: None,
+            tmp_data_dir: temp_dir.path().join("data"),
+            iteration: 0,
+            commit: "abc".to_string(),
+            params_dir: "default".to_string(),
+        };
+        let executor = ReindexHookExecutor::new(true);
+        assert_eq!(
+            executor.command_args(&args).unwrap(),
+            vec!["-reindex-chainstate", "-connect=0"]
+        );
+        executor.setup(&args).unwrap();
+
+        // Leave behind what a run would write
+        let datadir = args.tmp_data_dir.join("signet");
+        fs::create_dir_all(datadir.join("chainstate")).unwrap();
+        fs::write(datadir.join("blocks/index/000002.ldb"), "updated").unwrap();
+        fs::write(datadir.join("peers.dat"), "peers").unwrap();
+
+        executor.prepare(&args).unwrap();
+        assert!(!datadir.join("chainstate").exists());
+        assert!(!datadir.join("peers.dat").exists());
+        assert!(!datadir.join("blocks/index/000002.ldb").exists());
+        assert!(datadir.join("blocks/index/000001.ldb").exists());
+        assert!(datadir.join("blocks/blk00000.dat").exists());
+
+        // The blocks are staged once for every parameter set
+        fs::write(datadir.join("blocks/blk00001.dat"), "staged").unwrap();
+        fs::create_dir_all(datadir.join("chainstate")).unwrap();
+        executor.cleanup(&args).unwrap();
+        assert!(!datadir.join("chainstate").exists());
+        executor.setup(&args).unwrap();
+        assert!(datadir.join("blocks/blk00001.dat").exists());
+        drop(executor);
+        assert!(!datadir.exists());
+
+        // A full reindex rebuilds the block index
+        let executor = ReindexHookExecutor::new(false);
+        executor.setup(&args).unwrap();
+        executor.prepare(&args).unwrap();
+        assert!(!datadir.join("blocks/index").exists());
+        assert!(datadir.join("blocks/blk00000.dat").exists());
+    }
+
+    #[test]
+    fn test_load_block_command_args() {
+        let temp_dir = tempdir().unwrap();
+        let blocks = temp_dir.path().join("blocks");
+        fs::create_dir_all(&blocks).unwrap();
+        for name in ["blk00001.dat", "rev00000.dat", "blk00000.dat", "xor.dat"] {
+            fs::write(blocks.join(name), "").unwrap();
+        }
+        let bootstrap = temp_dir.path().join("bootstrap.dat");
+        fs::write(&bootstrap, "").unwrap();
+        let mut args = HookArgs {
+            binary: String::new(),
+            connect_address: String::new(),
+            network: "signet".to_string(),
+            out_dir: temp_dir.path().join("out"),
+            snapshot_path: PathBuf::new(),
+            source_datadir: None,
+            upgrade_from: None,
+            upgrade_height: None,
+            block_source: None,
+            load_blocks: vec![bootstrap.clone(), blocks.clone()],
+            prune: None,
+            tmp_data_dir: temp_dir.path().join("data"),
+            iteration: 0,
+            commit: "abc".to_string(),
+            params_dir: "default".to_string(),
+        };
+
+        let executor = LoadBlockHookExecutor::new();
+        assert_eq!(
+            executor.command_args(&args).unwrap(),
+            vec![
+                format!("-loadblock={}", bootstrap.display()),
+                format!("-loadblock={}", blocks.join("blk00000.dat").display()),
+                format!("-loadblock={}", blocks.join("blk00001.dat").display()),
+                "-connect=0".to_string(),
+            ]
+        );
+
+        args.load_blocks = vec![temp_dir.path().join("missing.dat")];
+        assert!(executor.command_args(&args).is_err());
+    }
 }
@@ -0,0 +1,387 @@
+use anyhow::Result;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use crate::benchmarks::procfs;
+use crate::cpu_binding::parse_cores_list;
+
+/// Default share of benchmark core capacity that other processes may use
+/// before a run is flagged
+pub const DEFAULT_INTERFERENCE_THRESHOLD: f64 = 5.0;
+
+/// Host-wide contention signals captured alongside a profile sample
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HostSample {
+    /// CPU pressure: share of time some tasks were stalled (avg10, percent)
+    pub psi_cpu_some: f64,
+    /// IO pressure: share of time some tasks were stalled (avg10, percent)
+    pub psi_io_some: f64,
+    /// IO pressure: share of time all tasks were stalled (avg10, percent)
+    pub psi_io_full: f64,
+    /// Memory pressure: share of time some tasks were stalled (avg10, percent)
+    pub psi_memory_some: f64,
+    /// Memory pressure: share of time all tasks were stalled (avg10, percent)
+    pub psi_memory_full: f64,
+    /// Utilisation of each benchmark core since the previous sample (percent)
+    pub core_utilisation: BTreeMap<u32, f64>,
+    /// Share of benchmark core capacity used by processes outside the
+    /// benchmarked tree since the previous sample (percent)
+    pub foreign_cpu: f64,
+    /// Reads completed on the datadir device since the previous sample
+    pub disk_reads: u64,
+    /// Writes completed on the datadir device since the previous sample
+    pub disk_writes: u64,
+    /// Sectors read from the datadir device since the previous sample
+    pub disk_sectors_read: u64,
+    /// Sectors written to the datadir device since the previous sample
+    pub disk_sectors_written: u64,
+    /// Milliseconds the datadir device was busy since the previous sample
+    pub disk_busy_ms: u64,
+}
+
+/// Summary of host contention over a whole run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InterferenceSummary {
+    /// Mean share of benchmark core capacity used by other processes (percent)
+    pub mean_foreign_cpu: f64,
+    /// Peak share of benchmark core capacity used by other processes (percent)
+    pub max_foreign_cpu: f64,
+    /// Mean CPU pressure (percent)
+    pub mean_psi_cpu_some: f64,
+    /// Mean IO pressure (percent)
+    pub mean_psi_io_some: f64,
+    /// Mean memory pressure (percent)
+    pub mean_psi_memory_some: f64,
+    /// Threshold the run was checked against (percent)
+    pub threshold: f64,
+    /// Whether interference from other load exceeded the threshold
+    pub flagged: bool,
+}
+
+impl InterferenceSummary {
+    /// Summarise the host samples of a run, flagging it if the mean share of
+    /// benchmark core capacity used by other processes exceeds `threshold`
+    pub fn from_samples(samples: &[&HostSample], threshold: f64) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+
+        let mean = |f: fn(&HostSample) -> f64| {
+            samples.iter().map(|s| f(s)).sum::<f64>() / samples.len() as f64
+        };
+        let mean_foreign_cpu = mean(|s| s.foreign_cpu);
+
+        Some(Self {
+            mean_foreign_cpu,
+            max_foreign_cpu: samples.iter().map(|s| s.foreign_cpu).fold(0.0, f64::max),
+            mean_psi_cpu_some: mean(|s| s.psi_cpu_some),
+            mean_psi_io_some: mean(|s| s.psi_io_some),
+            mean_psi_memory_some: mean(|s| s.psi_memory_some),
+            threshold,
+            flagged: mean_foreign_cpu > threshold,
+        })
+    }
+}
+
+/// Per-core tick counters from `/proc/stat`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CoreTicks {
+    /// Ticks spent doing work (everything except idle and iowait)
+    pub busy: u64,
+    /// All ticks
+    pub total: u64,
+}
+
+/// Counters for one block device from `/proc/diskstats`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiskCounters {
+    /// Reads completed
+    pub reads: u64,
+    /// Sectors read
+    pub sectors_read: u64,
+    /// Writes completed
+    pub writes: u64,
+    /// Sectors written
+    pub sectors_written: u64,
+    /// Milliseconds spent doing I/O
+    pub busy_ms: u64,
+}
+
+/// Samples host-wide contention signals between profiler samples
+pub struct HostMonitor {
+    /// Cores the benchmark is bound to (all cores if unbound)
+    cores: Option<Vec<u32>>,
+    /// Major/minor number of the device backing the datadir
+    device: Option<(u32, u32)>,
+    /// Core counters at the previous sample
+    prev_cores: BTreeMap<u32, CoreTicks>,
+    /// CPU ticks used by the benchmarked tree at the previous sample
+    prev_tree_ticks: u64,
+    /// Device counters at the previous sample
+    prev_disk: Option<DiskCounters>,
+}
+
+impl HostMonitor {
+    /// Create a monitor for the given benchmark cores and datadir
+    pub fn new(benchmark_cores: Option<&str>, datadir: &Path) -> Result<Self> {
+        let cores = benchmark_cores.map(parse_cores_list).transpose()?;
+        let device = device_for_path(datadir);
+        if device.is_none() {
+            debug!(
+                "Could not determine block device for {}, disk contention will not be sampled",
+                datadir.display()
+            );
+        }
+
+        let mut monitor = Self {
+            cores,
+            device,
+            prev_cores: BTreeMap::new(),
+            prev_tree_ticks: 0,
+            prev_disk: None,
+        };
+        // Establish a baseline so the first sample reports deltas
+        monitor.prev_cores = monitor.read_cores();
+        monitor.prev_disk = monitor.read_disk();
+
+        Ok(monitor)
+    }
+
+    /// Take a sample, excluding the CPU time of `tree_pids` from the
+    /// foreign load on the benchmark cores
+    pub fn sample(&mut self, tree_pids: &[u32]) -> HostSample {
+        let mut sample = HostSample::default();
+
+        for (resource, some, full) in [
+            ("cpu", &mut sample.psi_cpu_some, None),
+            ("io", &mut sample.psi_io_some, Some(&mut sample.psi_io_full)),
+            (
+                "memory",
+                &mut sample.psi_memory_some,
+                Some(&mut sample.psi_memory_full),
+            ),
+        ] {
+            if let Ok(contents) = fs::read_to_string(format!("/proc/pressure/{resource}")) {
+                let (s, f) = parse_pressure(&contents);
+                *some = s;
+                if let Some(full) = full {
+                    *full = f;
+                }
+            }
+        }
+
+        let cores = self.read_cores();
+        let mut busy_delta = 0;
+        let mut total_delta = 0;
+        for (core, ticks) in &cores {
+            if let Some(prev) = self.prev_cores.get(core) {
+                let busy = ticks.busy.saturating_sub(prev.busy);
+                let total = ticks.total.saturating_sub(prev.total);
+                if total > 0 {
+                    sample
+                        .core_utilisation
+                        .insert(*core, busy as f64 / total as f64 * 100.0);
+                }
+                busy_delta += busy;
+                total_delta += total;
+            }
+        }
+        self.prev_cores = cores;
+
+        // The tree is bound to the benchmark cores, so any busy time on those
+        // cores beyond its own was spent on other work
+        let tree_ticks = procfs::cpu_ticks(tree_pids);
+        let tree_delta = tree_ticks.saturating_sub(self.prev_tree_ticks);
+        self.prev_tree_ticks = tree_ticks;
+        if total_delta > 0 {
+            sample.foreign_cpu =
+                busy_delta.saturating_sub(tree_delta) as f64 / total_delta as f64 * 100.0;
+        }
+
+        let disk = self.read_disk();
+        if let (Some(prev), Some(current)) = (self.prev_disk, disk) {
+            sample.disk_reads = current.reads.saturating_sub(prev.reads);
+            sample.disk_writes = current.writes.saturating_sub(prev.writes);
+            sample.disk_sectors_read = current.sectors_read.saturating_sub(prev.sectors_read);
+            sample.disk_sectors_written =
+                current.sectors_written.saturating_sub(prev.sectors_written);
+            sample.disk_busy_ms = current.busy_ms.saturating_sub(prev.busy_ms);
+        }
+        self.prev_disk = disk;
+
+        sample
+    }
+
+    fn read_cores(&self) -> BTreeMap<u32, CoreTicks> {
+        match fs::read_to_string("/proc/stat") {
+            Ok(contents) => parse_core_ticks(&contents)
+                .into_iter()
+                .filter(|(core, _)| self.cores.as_ref().is_none_or(|c| c.contains(core)))
+                .collect(),
+            Err(e) => {
+                warn!("Failed to read /proc/stat: {e}");
+                BTreeMap::new()
+            }
+        }
+    }
+
+    fn read_disk(&self) -> Option<DiskCounters> {
+        let (major, minor) = self.device?;
+        let contents = fs::read_to_string("/proc/diskstats").ok()?;
+        parse_diskstats(&contents, major, minor)
+    }
+}
+
+/// Get the major/minor number of the block device backing `path`
+///
+/// Returns `None` for paths on virtual filesystems (tmpfs, overlay, ...),
+/// which report an anonymous device with major number 0.
+pub fn device_for_path(path: &Path) -> Option<(u32, u32)> {
+    let dev = fs::metadata(path).ok()?.dev();
+    let (major, minor) = (libc::major(dev), libc::minor(dev));
+    (major != 0).then_some((major, minor))
+}
+
+/// Parse the `avg10` values of the `some` and `full` lines of a PSI file
+pub fn parse_pressure(contents: &str) -> (f64, f64) {
+    let mut some = 0.0;
+    let mut full = 0.0;
+
+    for line in contents.lines() {
+        let mut parts = line.split_whitespace();
+        let target = match parts.next() {
+            Some("some") => &mut some,
+            Some("full") => &mut full,
+            _ => continue,
+        };
+        if let Some(avg10) = parts.find_map(|p| p.strip_prefix("avg10=")) {
+            *target = avg10.parse().unwrap_or(0.0);
+        }
+    }
+
+    (some, full)
+}
+
+/// Parse per-core tick counters from `/proc/stat`
+pub fn parse_core_ticks(contents: &str) -> BTreeMap<u32, CoreTicks> {
+    let mut cores = BTreeMap::new();
+
+    for line in contents.lines() {
+        let mut parts = line.split_whitespace();
+        let Some(core) = parts
+            .next()
+            .and_then(|name| name.strip_prefix("cpu"))
+            .and_then(|id| id.parse::<u32>().ok())
+        else {
+            continue;
+        };
+
+        // user nice system idle iowait irq softirq steal [guest guest_nice]
+        // guest time is already included in user/nice
+        let values: Vec<u64> = parts.take(8).filter_map(|v| v.parse().ok()).collect();
+        if values.len() < 8 {
+            continue;
+        }
+        let total: u64 = values.iter().sum();
+        let idle = values[3] + values[4];
+        cores.insert(
+            core,
+            CoreTicks {
+                busy: total - idle,
+                total,
+            },
+        );
+    }
+
+    cores
+}
+
+/// Parse the counters of one device from `/proc/diskstats`
+pub fn parse_diskstats(contents: &str, major: u32, minor: u32) -> Option<DiskCounters> {
+    contents.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 13
+            || fields[0].parse::<u32>().ok()? != major
+            || fields[1].parse::<u32>().ok()? != minor
+        {
+            return None;
+        }
+        let field = |i: usize| fields[i].parse::<u64>().ok();
+        Some(DiskCounters {
+            reads: field(3)?,
+            sectors_read: field(5)?,
+            writes: field(7)?,
+            sectors_written: field(9)?,
+            busy_ms: field(12)?,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pressure() {
+        let contents = "some avg10=3.19 avg60=1.33 avg300=3.42 total=30275133\n\
+                        full avg10=0.50 avg60=0.00 avg300=0.00 total=0\n";
+        assert_eq!(parse_pressure(contents), (3.19, 0.50));
+        assert_eq!(parse_pressure(""), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_parse_core_ticks() {
+        let contents = "cpu  200 0 40 700 10 0 0 0 0 0\n\
+                        cpu0 100 0 20 300 5 0 0 0 0 0\n\
+                        cpu1 100 0 20 400 5 0 0 0 0 0\n\
+                        intr 1 2 3\n";
+        let cores = parse_core_ticks(contents);
+        assert_eq!(cores.len(), 2);
+        assert_eq!(
+            cores[&0],
+            CoreTicks {
+                busy: 120,
+                total: 425
+            }
+        );
+        assert_eq!(cores[&1].busy, 120);
+    }
+
+    #[test]
+    fn test_parse_diskstats() {
+        let contents = "   7       0 loop0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n \
+                        259       0 nvme0n1 1000 5 80000 300 2000 10 160000 900 0 1200 1500\n";
+        let counters = parse_diskstats(contents, 259, 0).unwrap();
+        assert_eq!(counters.reads, 1000);
+        assert_eq!(counters.sectors_read, 80000);
+        assert_eq!(counters.writes, 2000);
+        assert_eq!(counters.sectors_written, 160000);
+        assert_eq!(counters.busy_ms, 1200);
+        assert!(parse_diskstats(contents, 8, 0).is_none());
+    }
+
+    #[test]
+    fn test_interference_summary() {
+        let quiet = HostSample {
+            foreign_cpu: 1.0,
+            ..HostSample::default()
+        };
+        let busy = HostSample {
+            foreign_cpu: 20.0,
+            ..HostSample::default()
+        };
+
+        let summary = InterferenceSummary::from_samples(&[&quiet, &quiet], 5.0).unwrap();
+        assert!(!summary.flagged);
+
+        let summary = InterferenceSummary::from_samples(&[&quiet, &busy], 5.0).unwrap();
+        assert!(summary.flagged);
+        assert_eq!(summary.max_foreign_cpu, 20.0);
+
+        assert!(InterferenceSummary::from_samples(&[], 5.0).is_none());
+    }
+}
@@ -1,115 +1,363 @@
 use anyhow::{Context, Result};
-use log::{debug, info, trace};
+use log::{debug, info, trace, warn};
 use regex::Regex;
-use std::io::{BufRead, BufReader};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
 use std::process::Child;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-/// Monitors process output for a specific regex pattern
+use crate::benchmarks::debug_log::parse_update_tip;
+
+/// What to do when a log rule matches
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogAction {
+    /// Stop the process; the run counts as completed
+    Stop,
+    /// Stop the process and mark the run as failed
+    Fail,
+    /// Record the time of the first match
+    Mark,
+    /// Start the named timer
+    StartTimer,
+    /// Stop the named timer, adding the time since it was started
+    StopTimer,
+}
+
+/// A regex matched against each line of output and the action taken when it
+/// matches. Named capture groups that parse as numbers are recorded as
+/// metrics, whatever the action.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LogRule {
+    pub pattern: String,
+    pub action: LogAction,
+    /// Name of the mark or timer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+impl LogRule {
+    /// A rule that stops the process when `pattern` matches
+    pub fn stop(pattern: impl Into<String>) -> Self {
+        Self {
+            pattern: pattern.into(),
+            action: LogAction::Stop,
+            name: None,
+        }
+    }
+
+    /// Check the pattern compiles and that marks and timers are named
+    pub fn validate(&self) -> Result<()> {
+        if self.pattern.is_empty() {
+            anyhow::bail!("Log rule pattern cannot be empty");
+        }
+        Regex::new(&self.pattern)
+            .with_context(|| format!("Invalid regex pattern in log rule: {}", self.pattern))?;
+        let needs_name = matches!(
+            self.action,
+            LogAction::Mark | LogAction::StartTimer | LogAction::StopTimer
+        );
+        if needs_name && self.name.as_deref().is_none_or(str::is_empty) {
+            anyhow::bail!(
+                "Log rule {:?} needs a name for its {:?} action",
+                self.pattern,
+                self.action
+            );
+        }
+        Ok(())
+    }
+}
+
+/// What the log rules observed over a run
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LogReport {
+    /// First line matched by a `fail` rule
+    pub failure: Option<String>,
+    /// Time of the first match of each mark since monitoring started
+    pub marks: BTreeMap<String, Duration>,
+    /// Total time each timer ran for
+    pub timers: BTreeMap<String, Duration>,
+    /// Last value of each named capture group
+    pub captures: BTreeMap<String, f64>,
+    /// Time the tip first reached each milestone height
+    pub milestones: BTreeMap<u64, Duration>,
+}
+
+impl LogReport {
+    /// Marks and timers in milliseconds as `<name>_ms`, captures under
+    /// their group name and milestones as `time_to_height_<N>_ms`
+    pub fn metrics(&self) -> impl Iterator<Item = (String, f64)> + '_ {
+        let millis = |time: &Duration| time.as_secs_f64() * 1000.0;
+        self.marks
+            .iter()
+            .chain(&self.timers)
+            .map(move |(name, time)| (format!("{name}_ms"), millis(time)))
+            .chain(self.captures.iter().map(|(name, v)| (name.clone(), *v)))
+            .chain(
+                self.milestones.iter().map(move |(height, time)| {
+                    (format!("time_to_height_{height}_ms"), millis(time))
+                }),
+            )
+    }
+}
+
+/// A log rule with its compiled regex
+struct CompiledRule {
+    rule: LogRule,
+    regex: Regex,
+}
+
+/// State shared between the stream readers
+struct RuleState {
+    start: Instant,
+    rules: Vec<CompiledRule>,
+    /// Heights whose time to reach is recorded from `UpdateTip` lines
+    milestones: Vec<u64>,
+    /// Set when a `stop` rule matches
+    matched: Arc<AtomicBool>,
+    report: Mutex<LogReport>,
+    running_timers: Mutex<HashMap<String, Instant>>,
+}
+
+impl RuleState {
+    /// Compile the rules, with monitoring starting now
+    fn new(rules: Vec<LogRule>, milestones: Vec<u64>) -> Result<Self> {
+        let rules = rules
+            .into_iter()
+            .map(|rule| {
+                let regex = Regex::new(&rule.pattern).with_context(|| {
+                    format!("Failed to compile regex pattern: {}", rule.pattern)
+                })?;
+                Ok(CompiledRule { rule, regex })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            start: Instant::now(),
+            rules,
+            milestones,
+            matched: Arc::new(AtomicBool::new(false)),
+            report: Mutex::new(LogReport::default()),
+            running_timers: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Apply every rule to a line of output
+    fn apply(&self, line: &str, stream_name: &str) {
+        if !self.milestones.is_empty() {
+            self.track_milestones(line);
+        }
+        // Every rule matching the line sees it at the same time
+        let now = Instant::now();
+        for CompiledRule { rule, regex } in &self.rules {
+            let Some(captures) = regex.captures(line) else {
+                continue;
+            };
+            let name = rule.name.clone().unwrap_or_default();
+            let mut report = self.report.lock().unwrap();
+
+            match rule.action {
+                LogAction::Stop => {
+                    if !self.matched.swap(true, Ordering::SeqCst) {
+                        info!("Pattern matched in {stream_name} line: {line}");
+                    }
+                }
+                LogAction::Fail => {
+                    if report.failure.is_none() {
+                        warn!("Failure pattern matched in {stream_name} line: {line}");
+                        report.failure = Some(line.to_string());
+                    }
+                }
+                LogAction::Mark => {
+                    report
+                        .marks
+                        .entry(name)
+                        .or_insert_with(|| now.duration_since(self.start));
+                }
+                LogAction::StartTimer => {
+                    self.running_timers
+                        .lock()
+                        .unwrap()
+                        .entry(name)
+                        .or_insert(now);
+                }
+                LogAction::StopTimer => {
+                    if let Some(started) = self.running_timers.lock().unwrap().remove(&name) {
+                        *report.timers.entry(name).or_default() += now.duration_since(started);
+                    }
+                }
+            }
+
+            for group in regex.capture_names().flatten() {
+                if let Some(value) = captures.name(group).and_then(|m| m.as_str().parse().ok()) {
+                    report.captures.insert(group.to_string(), value);
+                }
+            }
+        }
+    }
+
+    /// Record the milestones a tip update reaches for the first time
+    fn track_milestones(&self, line: &str) {
+        let Some(tip) = parse_update_tip(line) else {
+            return;
+        };
+        let elapsed = self.start.elapsed();
+        let mut report = self.report.lock().unwrap();
+        for &height in self.milestones.iter().filter(|&&h| h <= tip.height) {
+            report.milestones.entry(height).or_insert_with(|| {
+                info!("Reached milestone height {height} after {elapsed:?}");
+                elapsed
+            });
+        }
+    }
+}
+
+/// How often a followed log file is checked for new lines
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Monitors process output against a set of log rules
+///
+/// Rules are applied either to the output streams of a process, which are
+/// read until they close so the process never blocks on a full pipe and can
+/// optionally be kept for the results, or to a log file on disk that is
+/// followed like `tail -F`.
 pub struct LogMonitor {
-    /// The pattern to search for
-    pattern: String,
-    /// Flag indicating if the pattern was matched
+    /// Rules applied to each line, shared with the reader threads
+    state: Arc<RuleState>,
+    /// Flag indicating if a `stop` rule was matched
     matched: Arc<AtomicBool>,
-    /// Thread handles for stdout and stderr readers
+    /// Captured stdout, if capturing
+    stdout: Option<Arc<Mutex<Vec<u8>>>>,
+    /// Captured stderr, if capturing
+    stderr: Option<Arc<Mutex<Vec<u8>>>>,
+    /// Thread handles for stdout and stderr readers or the file follower
     reader_threads: Vec<thread::JoinHandle<Result<()>>>,
+    /// Tells a file follower to read what is left and exit
+    stop: Arc<AtomicBool>,
 }
 
 impl LogMonitor {
-    /// Start monitoring a child process for a specific regex pattern
-    pub fn start_monitoring(child: &mut Child, pattern: String) -> Result<Self> {
-        debug!("Starting log monitor for pattern: {pattern}");
-
-        // Create and compile the regex pattern
-        let regex = Regex::new(&pattern).context("Failed to compile regex pattern")?;
-        let regex = Arc::new(regex);
+    /// Start reading the output of a child process, applying `rules` to each
+    /// line, recording when the tip reaches each of `milestones` and keeping
+    /// the output if `capture` is set
+    pub fn start(
+        child: &mut Child,
+        rules: Vec<LogRule>,
+        milestones: Vec<u64>,
+        capture: bool,
+    ) -> Result<Self> {
+        debug!("Starting log monitor for rules: {rules:?}");
 
-        let matched = Arc::new(AtomicBool::new(false));
+        let state = Arc::new(RuleState::new(rules, milestones)?);
+        let capture_buffer = || capture.then(|| Arc::new(Mutex::new(Vec::new())));
+        let stdout_buffer = capture_buffer();
+        let stderr_buffer = capture_buffer();
         let mut reader_threads = Vec::new();
 
         // Take stdout if available
         if let Some(stdout) = child.stdout.take() {
-            let thread_regex = Arc::clone(&regex);
-            let thread_matched = Arc::clone(&matched);
+            let thread_state = Arc::clone(&state);
+            let thread_buffer = stdout_buffer.clone();
 
             let handle = thread::spawn(move || {
-                monitor_stream_with_regex(stdout, thread_regex, thread_matched, "stdout")
+                monitor_stream(stdout, thread_state, thread_buffer, "stdout")
             });
             reader_threads.push(handle);
         }
 
         // Take stderr if available
         if let Some(stderr) = child.stderr.take() {
-            let thread_regex = Arc::clone(&regex);
-            let thread_matched = Arc::clone(&matched);
+            let thread_state = Arc::clone(&state);
+            let thread_buffer = stderr_buffer.clone();
 
             let handle = thread::spawn(move || {
-                monitor_stream_with_regex(stderr, thread_regex, thread_matched, "stderr")
+                monitor_stream(stderr, thread_state, thread_buffer, "stderr")
             });
             reader_threads.push(handle);
         }
 
         Ok(LogMonitor {
-            pattern,
-            matched,
+            matched: Arc::clone(&state.matched),
+            state,
+            stdout: stdout_buffer,
+            stderr: stderr_buffer,
             reader_threads,
+            stop: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// Follow a log file on disk, applying `rules` to each line written
+    /// after `offset` and recording when the tip reaches each of `milestones`
+    ///
+    /// The file does not need to exist yet, and is reopened if it is
+    /// truncated, replaced or removed, so rotation is handled.
+    pub fn follow(
+        path: &Path,
+        rules: Vec<LogRule>,
+        milestones: Vec<u64>,
+        offset: u64,
+    ) -> Result<Self> {
+        debug!(
+            "Following {} from offset {offset} for rules: {rules:?}",
+            path.display()
+        );
+
+        let state = Arc::new(RuleState::new(rules, milestones)?);
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_state = Arc::clone(&state);
+        let thread_stop = Arc::clone(&stop);
+        let path = path.to_path_buf();
+        let handle = thread::spawn(move || follow_file(&path, offset, &thread_state, &thread_stop));
+
+        Ok(LogMonitor {
+            matched: Arc::clone(&state.matched),
+            state,
+            stdout: None,
+            stderr: None,
+            reader_threads: vec![handle],
+            stop,
         })
     }
 
-    /// Check if the pattern has been matched
+    /// Wait for the output streams to close and return the captured stdout
+    /// and stderr (empty unless capturing)
+    pub fn take_output(&mut self) -> (Vec<u8>, Vec<u8>) {
+        self.cleanup_threads();
+        let take = |buffer: &Option<Arc<Mutex<Vec<u8>>>>| {
+            buffer
+                .as_ref()
+                .map(|b| std::mem::take(&mut *b.lock().unwrap()))
+                .unwrap_or_default()
+        };
+        (take(&self.stdout), take(&self.stderr))
+    }
+
+    /// Check if a `stop` rule has been matched
     pub fn is_matched(&self) -> bool {
         self.matched.load(Ordering::SeqCst)
     }
 
-    /// Wait for either the pattern to be matched or the process to exit
-    /// Returns true if pattern was matched, false if process exited
-    pub fn wait_for_match_or_exit(
-        &mut self,
-        child: &mut Child,
-        check_interval: Duration,
-    ) -> Result<bool> {
-        debug!("Waiting for pattern match or process exit");
-        let mut check_count = 0;
-
-        loop {
-            check_count += 1;
-
-            // Check if process has exited
-            match child.try_wait()? {
-                Some(status) => {
-                    debug!(
-                        "Process exited with status {status} before pattern match (after {check_count} checks)"
-                    );
-                    self.cleanup_threads();
-                    return Ok(false);
-                }
-                None => {
-                    // Process still running, check for match
-                    if self.is_matched() {
-                        info!(
-                            "Pattern '{}' matched in process output after {} checks",
-                            self.pattern, check_count
-                        );
-                        return Ok(true);
-                    }
-
-                    // Log periodic status
-                    if check_count % 100 == 0 {
-                        debug!("Still waiting for pattern match after {check_count} checks, process still running");
-                    }
+    /// The first line matched by a `fail` rule, if any
+    pub fn failure(&self) -> Option<String> {
+        self.state.report.lock().unwrap().failure.clone()
+    }
 
-                    // Sleep before next check
-                    thread::sleep(check_interval);
-                }
-            }
-        }
+    /// What the rules have observed so far
+    pub fn report(&self) -> LogReport {
+        self.state.report.lock().unwrap().clone()
     }
 
     /// Clean up reader threads
     fn cleanup_threads(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
         for handle in self.reader_threads.drain(..) {
             match handle.join() {
                 Ok(Ok(())) => {}
@@ -126,11 +374,12 @@ impl Drop for LogMonitor {
     }
 }
 
-/// Monitor a single stream for the pattern using regex
-fn monitor_stream_with_regex<R: std::io::Read + Send + 'static>(
+/// Apply the log rules to each line of a single stream, reading until the
+/// stream closes
+fn monitor_stream<R: std::io::Read + Send + 'static>(
     stream: R,
-    regex: Arc<Regex>,
-    matched: Arc<AtomicBool>,
+    state: Arc<RuleState>,
+    capture: Option<Arc<Mutex<Vec<u8>>>>,
     stream_name: &str,
 ) -> Result<()> {
     let reader = BufReader::new(stream);
@@ -146,14 +395,13 @@ fn monitor_stream_with_regex<R: std::io::Read + Send + 'static>(
                 // Log every line to trace what we're receiving
                 trace!("{stream_name}:{line_count} - {line}");
 
-                // Check if line matches the pattern using regex
-                if regex.is_match(&line) {
-                    info!("REGEX MATCH: line={line}");
-                    info!("Pattern matched in {stream_name} line: {line}");
-                    matched.store(true, Ordering::SeqCst);
-                    trace!("Set matched flag to true in {stream_name} thread");
-                    break;
+                if let Some(buffer) = &capture {
+                    let mut buffer = buffer.lock().unwrap();
+                    buffer.extend_from_slice(line.as_bytes());
+                    buffer.push(b'\n');
                 }
+
+                state.apply(&line, stream_name);
             }
             Err(e) => {
                 // EOF or other error, stop reading
@@ -167,33 +415,194 @@ fn monitor_stream_with_regex<R: std::io::Read + Send + 'static>(
     Ok(())
 }
 
-/// Builder for LogMonitor with configurable options
-pub struct LogMonitorBuilder {
-    check_interval: Duration,
+/// A log file being followed, with the identity of the open file
+struct FollowedFile {
+    reader: BufReader<File>,
+    inode: u64,
+    position: u64,
 }
 
-impl Default for LogMonitorBuilder {
-    fn default() -> Self {
-        Self::new()
+impl FollowedFile {
+    fn open(path: &Path, offset: u64) -> Option<Self> {
+        let mut file = File::open(path).ok()?;
+        let metadata = file.metadata().ok()?;
+        // Start from the beginning if the file is shorter than the offset
+        let position = if metadata.len() >= offset { offset } else { 0 };
+        file.seek(SeekFrom::Start(position)).ok()?;
+        Some(Self {
+            reader: BufReader::new(file),
+            inode: metadata.ino(),
+            position,
+        })
     }
 }
 
-impl LogMonitorBuilder {
-    /// Create a new LogMonitorBuilder
-    pub fn new() -> Self {
-        Self {
-            check_interval: Duration::from_millis(100),
+/// Apply the log rules to each line appended to a file until told to stop,
+/// reading whatever is left when stopping
+fn follow_file(path: &Path, offset: u64, state: &RuleState, stop: &AtomicBool) -> Result<()> {
+    let mut followed: Option<FollowedFile> = None;
+    let mut offset = offset;
+    let mut line = Vec::new();
+    let mut line_count = 0;
+
+    loop {
+        let stopping = stop.load(Ordering::SeqCst);
+
+        if followed.is_none() {
+            followed = FollowedFile::open(path, offset);
+            if followed.is_some() {
+                debug!("Opened {} for following", path.display());
+            }
+        }
+
+        if let Some(file) = followed.as_mut() {
+            // Read all complete lines, keeping any partial line for later
+            loop {
+                let read = file.reader.read_until(b'\n', &mut line)?;
+                if read == 0 {
+                    break;
+                }
+                file.position += read as u64;
+                if line.ends_with(b"\n") {
+                    line_count += 1;
+                    let text = String::from_utf8_lossy(&line);
+                    trace!("{}:{line_count} - {}", path.display(), text.trim_end());
+                    state.apply(text.trim_end_matches(['\r', '\n']), "log file");
+                    line.clear();
+                }
+            }
+
+            // Reopen the file if it was truncated, replaced or removed
+            let reopen = match std::fs::metadata(path) {
+                Ok(metadata) if metadata.ino() != file.inode => {
+                    debug!("{} was replaced, reopening", path.display());
+                    true
+                }
+                Ok(metadata) if metadata.len() < file.position => {
+                    debug!("{} was truncated, reading from the start", path.display());
+                    true
+                }
+                Ok(_) => false,
+                Err(_) => {
+                    debug!("{} was removed, waiting for it to reappear", path.display());
+                    true
+                }
+            };
+            if reopen {
+                followed = None;
+                offset = 0;
+                line.clear();
+                // Read the new file straight away
+                continue;
+            }
         }
+
+        if stopping {
+            break;
+        }
+        thread::sleep(FOLLOW_INTERVAL);
     }
 
-    /// Set the interval for checking match status
-    pub fn check_interval(mut self, interval: Duration) -> Self {
-        self.check_interval = interval;
-        self
+    debug!(
+        "Finished following {} after {line_count} lines",
+        path.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn append(path: &Path, line: &str) {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        writeln!(file, "{line}").unwrap();
     }
 
-    /// Build and start monitoring
-    pub fn start(self, child: &mut Child, pattern: String) -> Result<LogMonitor> {
-        LogMonitor::start_monitoring(child, pattern)
+    #[test]
+    fn test_follow_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("debug.log");
+        let settle = || thread::sleep(FOLLOW_INTERVAL * 3);
+
+        // Lines before the offset are skipped
+        append(&path, "height=1");
+        let offset = std::fs::metadata(&path).unwrap().len();
+        let rule = |pattern: &str, action, name: &str| LogRule {
+            pattern: pattern.to_string(),
+            action,
+            name: Some(name.to_string()),
+        };
+        let mut monitor = LogMonitor::follow(
+            &path,
+            vec![
+                rule("height=(?P<height>[0-9]+)", LogAction::Mark, "tip"),
+                rule("rotated", LogAction::Mark, "rotated"),
+                LogRule::stop("height=3"),
+            ],
+            Vec::new(),
+            offset,
+        )
+        .unwrap();
+        settle();
+        assert!(monitor.report().captures.is_empty());
+
+        // Truncation
+        append(&path, "height=2");
+        settle();
+        std::fs::write(&path, "").unwrap();
+        settle();
+        append(&path, "rotated by truncation");
+        settle();
+        assert_eq!(monitor.report().captures["height"], 2.0);
+        assert!(monitor.report().marks.contains_key("rotated"));
+
+        // Rotation to a new file
+        std::fs::rename(&path, dir.path().join("debug.log.1")).unwrap();
+        settle();
+        append(&path, "height=3");
+        monitor.take_output();
+        assert!(monitor.is_matched());
+        assert_eq!(monitor.report().captures["height"], 3.0);
+    }
+
+    #[test]
+    fn test_milestones() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("debug.log");
+        let tip = |height: u64| {
+            format!("2025-01-31T12:00:00Z UpdateTip: new best=00 height={height} tx=1 progress=0.5")
+        };
+
+        let mut monitor =
+            LogMonitor::follow(&path, Vec::new(), vec![100, 150, 200, 300], 0).unwrap();
+        append(&path, &tip(99));
+        append(&path, "2025-01-31T12:00:01Z height=300 is not a tip update");
+        // A tip update can pass several milestones at once
+        append(&path, &tip(150));
+        append(&path, &tip(250));
+        monitor.take_output();
+        let report = monitor.report();
+
+        assert_eq!(
+            report.milestones.keys().collect::<Vec<_>>(),
+            [&100, &150, &200]
+        );
+        assert_eq!(report.milestones[&100], report.milestones[&150]);
+        assert!(report.milestones[&150] <= report.milestones[&200]);
+        let metrics: BTreeMap<String, f64> = report.metrics().collect();
+        assert_eq!(
+            metrics.keys().collect::<Vec<_>>(),
+            [
+                "time_to_height_100_ms",
+                "time_to_height_150_ms",
+                "time_to_height_200_ms"
+            ]
+        );
     }
 }
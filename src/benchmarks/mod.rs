@@ -13,6 +13,10 @@
 //! - `ParameterMatrix`: Manages parameter substitution for commands
 //! - `ResultExporter`: Exports benchmark results to various formats
 //! - `Profiler`: Collects performance metrics during benchmark runs
+//! - `RpcCollector`: Polls node state over RPC during benchmark runs
+//! - `Timeline`: Combines the profiles of all runs of a commit and parameter set
+//! - `ProcessSupervisor`: Owns a running benchmark and drives profiling, log matching, stall
+//!   detection and timeouts for it
 
 mod build;
 pub use build::Builder;
@@ -21,13 +25,28 @@ mod repository;
 pub use repository::{RepoSource, RepositoryManager};
 
 mod hook_runner;
-pub use hook_runner::{HookArgs, HookRunner, HookStage};
+pub use hook_runner::{
+    HookArgs, HookPosition, HookRecord, HookRunner, HookStage, ScriptHooks, StageScripts,
+};
 
 mod hooks;
-pub use hooks::{AssumeUtxoHookExecutor, FullIbdHookExecutor, HookExecutor, HookMode};
+pub use hooks::{
+    AssumeUtxoHookExecutor, DatadirHookExecutor, FullIbdHookExecutor, HookExecutor, HookMode,
+    LoadBlockHookExecutor, PrunedHookExecutor, ReindexHookExecutor, UpgradeHookExecutor,
+};
+
+mod datadir;
+
+mod peer;
+pub use peer::{ManagedPeer, PeerConfig};
+
+mod prune;
+pub use prune::{BlocksMonitor, BlocksSample, PruneConfig, PruneResult};
 
 mod results;
-pub use results::{BenchmarkResult, InstrumentationType, RunResult, RunSummary};
+pub use results::{
+    BenchmarkResult, InstrumentationType, PhaseTiming, RunResult, RunStatus, RunSummary,
+};
 
 mod parameters;
 pub use parameters::{ParameterList, ParameterMatrix};
@@ -36,7 +55,28 @@ mod export;
 pub use export::ResultExporter;
 
 mod profiler;
-pub use profiler::{ProfileSample, Profiler};
+pub use profiler::{ProfileSample, ProfileSession, Profiler};
+
+mod procfs;
+pub use procfs::ProcStats;
+
+mod stall;
+pub use stall::{StallConfig, StallDetector};
+
+mod debug_log;
+pub use debug_log::TipUpdate;
+
+mod chain_state;
+pub use chain_state::ChainState;
+
+mod rpc;
+pub use rpc::{RpcClient, RpcCollection, RpcCollector, RpcData, RpcSample};
+
+mod timeline;
+pub use timeline::{Timeline, TimelineAlignment, TimelinePoint};
+
+mod host_monitor;
+pub use host_monitor::{HostMonitor, HostSample, InterferenceSummary};
 
 mod benchmark_runner;
 pub use benchmark_runner::BenchmarkRunner;
@@ -44,11 +84,17 @@ pub use benchmark_runner::BenchmarkRunner;
 mod runner;
 pub use runner::Runner;
 
+mod supervisor;
+pub use supervisor::{
+    MeasureMarker, ProcessSupervisor, ProcessSupervisorBuilder, ResourceUsage, StopReason,
+    SupervisedRun,
+};
+
 mod log_monitor;
-pub use log_monitor::{LogMonitor, LogMonitorBuilder};
+pub use log_monitor::{LogAction, LogMonitor, LogReport, LogRule};
 
 mod perf;
 pub use perf::{PerfInstrumentor, PerfInstrumentorBuilder};
 
 mod utils;
-pub use utils::{binary_exists, check_binaries_exist, get_binary_path};
+pub use utils::{binary_exists, check_binaries_exist, get_binary_path, get_network_datadir};
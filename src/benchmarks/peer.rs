@@ -0,0 +1,200 @@
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fs;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::Child;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::benchmarks::datadir::restore_datadir;
+use crate::benchmarks::rpc::RpcClient;
+use crate::benchmarks::utils::get_network_datadir;
+use crate::command::CommandExecutor;
+
+/// How long the peer is given to load its datadir and answer RPC calls
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Configuration of a peer benchkit runs to serve blocks to the benchmark
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PeerConfig {
+    /// Reference bitcoind binary the peer runs
+    pub binary: PathBuf,
+    /// Synced datadir the peer serves blocks from. The peer runs on a copy,
+    /// so it is never modified.
+    pub datadir: PathBuf,
+    /// Cores the peer is pinned to, which must not overlap benchmark_cores.
+    /// Defaults to runner_cores.
+    #[serde(default)]
+    pub cores: Option<String>,
+    /// Extra arguments for the peer
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// A block-serving peer owned by benchkit for the length of a benchmark.
+/// It is stopped, and its datadir copy removed, when dropped.
+pub struct ManagedPeer {
+    child: Mutex<Child>,
+    client: RpcClient,
+    /// Address the benchmarked node connects to
+    address: String,
+    /// Copy of the configured datadir the peer runs on
+    datadir: PathBuf,
+    network: String,
+    /// Height the peer had when it started, which it should keep
+    height: u64,
+    /// Where the peer's debug.log is saved when it stops
+    log_dest: PathBuf,
+}
+
+impl ManagedPeer {
+    /// Start a peer on a copy of the configured datadir at `datadir`, on free
+    /// ports, and wait for it to answer RPC calls
+    pub fn start(
+        config: &PeerConfig,
+        cores: Option<String>,
+        network: &str,
+        datadir: &Path,
+        out_dir: &Path,
+    ) -> Result<Self> {
+        if datadir.exists() {
+            fs::remove_dir_all(datadir)
+                .with_context(|| format!("Failed to remove {}", datadir.display()))?;
+        }
+        restore_datadir(&config.datadir, datadir)?;
+
+        let port = free_port()?;
+        let rpc_port = free_port()?;
+        let mut args = vec![
+            format!("-chain={network}"),
+            format!("-datadir={}", datadir.display()),
+            format!("-bind=127.0.0.1:{port}"),
+            format!("-rpcport={rpc_port}"),
+            // Only accept connections, as -connect=0 also disables listening
+            "-connect=0".to_string(),
+            "-listen=1".to_string(),
+            "-whitelist=noban,download@127.0.0.1".to_string(),
+            "-printtoconsole=0".to_string(),
+        ];
+        args.extend(config.args.iter().cloned());
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+        let binary = config.binary.to_string_lossy();
+        info!("Starting peer {binary} on port {port}");
+        let mut child = CommandExecutor::builder()
+            .cpu_cores(cores)
+            .build()?
+            .launch_command(&binary, &args)?;
+
+        let network_datadir = get_network_datadir(datadir, network);
+        let client = RpcClient::new(rpc_port, &network_datadir.join(".cookie"))?;
+        let height = client
+            .wait_until_ready(&mut child, STARTUP_TIMEOUT)
+            .and_then(|()| block_count(&client));
+
+        let mut peer = Self {
+            child: Mutex::new(child),
+            client,
+            address: format!("127.0.0.1:{port}"),
+            datadir: datadir.to_path_buf(),
+            network: network.to_string(),
+            height: 0,
+            log_dest: out_dir.join("peer-debug.log"),
+        };
+        // Dropping the peer stops it if it could not be queried
+        peer.height = height.context("Peer failed to start")?;
+        info!("Peer is serving {} blocks at {}", peer.height, peer.address);
+
+        Ok(peer)
+    }
+
+    /// Address the benchmarked node connects to
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Check the peer is still running and serving the chain it started with
+    pub fn check_health(&self) -> Result<()> {
+        if let Some(status) = self.child.lock().unwrap().try_wait()? {
+            anyhow::bail!("Peer exited with {status}");
+        }
+        let height = block_count(&self.client).context("Peer is not answering RPC calls")?;
+        if height != self.height {
+            anyhow::bail!("Peer is at height {height}, but started at {}", self.height);
+        }
+        debug!("Peer is healthy at height {height}");
+        Ok(())
+    }
+}
+
+impl Drop for ManagedPeer {
+    fn drop(&mut self) {
+        info!("Stopping peer");
+        let child = self.child.get_mut().unwrap();
+        if child.try_wait().ok().flatten().is_none() {
+            if let Err(e) = self.client.call("stop", json!([])) {
+                debug!("Failed to stop peer over RPC, killing it: {e:#}");
+                child.kill().ok();
+            }
+            child.wait().ok();
+        }
+
+        let log = get_network_datadir(&self.datadir, &self.network).join("debug.log");
+        if let Err(e) = fs::copy(&log, &self.log_dest) {
+            warn!("Failed to save peer debug.log: {e}");
+        }
+        if let Err(e) = fs::remove_dir_all(&self.datadir) {
+            warn!("Failed to remove {}: {e}", self.datadir.display());
+        }
+    }
+}
+
+fn block_count(client: &RpcClient) -> Result<u64> {
+    client
+        .call("getblockcount", json!([]))?
+        .as_u64()
+        .context("Invalid getblockcount response")
+}
+
+/// A port on localhost that nothing is listening on
+fn free_port() -> Result<u16> {
+    let listener = TcpListener::bind("127.0.0.1:0").context("Failed to find a free port")?;
+    Ok(listener.local_addr()?.port())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_health() {
+        let dir = tempfile::tempdir().unwrap();
+        let peer = |child: Child| ManagedPeer {
+            child: Mutex::new(child),
+            client: RpcClient::new(free_port().unwrap(), &dir.path().join(".cookie")).unwrap(),
+            address: String::new(),
+            datadir: dir.path().join("peer"),
+            network: "signet".to_string(),
+            height: 100,
+            log_dest: dir.path().join("peer-debug.log"),
+        };
+
+        let running = peer(
+            CommandExecutor::new()
+                .launch_command("sleep", &["10"])
+                .unwrap(),
+        );
+        let err = running.check_health().unwrap_err();
+        assert!(err.to_string().contains("not answering"));
+        drop(running);
+
+        let mut child = CommandExecutor::new().launch_command("true", &[]).unwrap();
+        child.wait().unwrap();
+        let err = peer(child).check_health().unwrap_err();
+        assert!(err.to_string().contains("exited"));
+    }
+}
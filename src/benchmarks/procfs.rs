@@ -0,0 +1,333 @@
+use std::fs;
+use std::ops::AddAssign;
+use std::path::Path;
+
+/// Per-process counters read from `/proc/<pid>`
+///
+/// All values are cumulative for the lifetime of the process, except for the
+/// thread count, open file descriptors and RSS split which are point-in-time.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProcStats {
+    /// Voluntary context switches
+    pub voluntary_ctxt_switches: u64,
+    /// Involuntary context switches
+    pub involuntary_ctxt_switches: u64,
+    /// Minor page faults
+    pub minor_faults: u64,
+    /// Major page faults
+    pub major_faults: u64,
+    /// Number of threads
+    pub threads: u64,
+    /// Number of open file descriptors
+    pub open_fds: u64,
+    /// Anonymous resident memory in bytes
+    pub rss_anon: u64,
+    /// File-backed resident memory in bytes
+    pub rss_file: u64,
+    /// Shared memory resident in bytes
+    pub rss_shmem: u64,
+    /// Bytes read from the storage layer
+    pub read_bytes: u64,
+    /// Bytes written to the storage layer
+    pub write_bytes: u64,
+    /// Bytes whose writeback was cancelled (e.g. truncated before flushing)
+    pub cancelled_write_bytes: u64,
+}
+
+impl ProcStats {
+    /// Read the counters for a single process
+    ///
+    /// Files that cannot be read (e.g. the process exited between samples, or
+    /// `/proc/<pid>/io` is restricted) leave their fields at zero.
+    pub fn read(pid: u32) -> Self {
+        let mut stats = Self::default();
+        let base = format!("/proc/{pid}");
+
+        if let Ok(contents) = fs::read_to_string(format!("{base}/status")) {
+            parse_status(&contents, &mut stats);
+        }
+        // The process status only counts the context switches of the main
+        // thread, so those of every thread are summed
+        if let Ok(tasks) = fs::read_dir(format!("{base}/task")) {
+            let statuses: Vec<String> = tasks
+                .flatten()
+                .filter_map(|task| fs::read_to_string(task.path().join("status")).ok())
+                .collect();
+            parse_task_statuses(statuses.iter().map(String::as_str), &mut stats);
+        }
+        if let Ok(contents) = fs::read_to_string(format!("{base}/stat")) {
+            if let Some(fields) = parse_stat(&contents) {
+                stats.minor_faults = fields.minor_faults;
+                stats.major_faults = fields.major_faults;
+            }
+        }
+        if let Ok(contents) = fs::read_to_string(format!("{base}/smaps_rollup")) {
+            parse_smaps_rollup(&contents, &mut stats);
+        }
+        if let Ok(contents) = fs::read_to_string(format!("{base}/io")) {
+            parse_io(&contents, &mut stats);
+        }
+        if let Ok(entries) = fs::read_dir(format!("{base}/fd")) {
+            stats.open_fds = entries.count() as u64;
+        }
+
+        stats
+    }
+}
+
+impl AddAssign<&ProcStats> for ProcStats {
+    fn add_assign(&mut self, other: &ProcStats) {
+        self.voluntary_ctxt_switches += other.voluntary_ctxt_switches;
+        self.involuntary_ctxt_switches += other.involuntary_ctxt_switches;
+        self.minor_faults += other.minor_faults;
+        self.major_faults += other.major_faults;
+        self.threads += other.threads;
+        self.open_fds += other.open_fds;
+        self.rss_anon += other.rss_anon;
+        self.rss_file += other.rss_file;
+        self.rss_shmem += other.rss_shmem;
+        self.read_bytes += other.read_bytes;
+        self.write_bytes += other.write_bytes;
+        self.cancelled_write_bytes += other.cancelled_write_bytes;
+    }
+}
+
+/// Selected fields from `/proc/<pid>/stat`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StatFields {
+    /// Minor page faults
+    pub minor_faults: u64,
+    /// Major page faults
+    pub major_faults: u64,
+    /// CPU time spent in user mode, in clock ticks
+    pub utime: u64,
+    /// CPU time spent in kernel mode, in clock ticks
+    pub stime: u64,
+}
+
+/// Parse `/proc/<pid>/stat`
+///
+/// The command name is wrapped in parentheses and may itself contain spaces or
+/// parentheses, so fields are counted from the last closing parenthesis.
+pub fn parse_stat(contents: &str) -> Option<StatFields> {
+    let rest = &contents[contents.rfind(')')? + 1..];
+    let fields: Vec<&str> = rest.split_whitespace().collect();
+    // `fields[0]` is field 3 (state) in proc(5) numbering
+    let field = |n: usize| fields.get(n - 3).and_then(|v| v.parse::<u64>().ok());
+
+    Some(StatFields {
+        minor_faults: field(10)?,
+        major_faults: field(12)?,
+        utime: field(14)?,
+        stime: field(15)?,
+    })
+}
+
+/// Total user and kernel CPU time of a set of processes, in clock ticks
+pub fn cpu_ticks(pids: &[u32]) -> u64 {
+    pids.iter()
+        .filter_map(|pid| fs::read_to_string(format!("/proc/{pid}/stat")).ok())
+        .filter_map(|contents| parse_stat(&contents))
+        .map(|fields| fields.utime + fields.stime)
+        .sum()
+}
+
+/// Total bytes read from and written to the storage layer by a set of
+/// processes
+pub fn io_bytes(pids: &[u32]) -> u64 {
+    pids.iter()
+        .filter_map(|pid| fs::read_to_string(format!("/proc/{pid}/io")).ok())
+        .map(|contents| {
+            let mut stats = ProcStats::default();
+            parse_io(&contents, &mut stats);
+            stats.read_bytes + stats.write_bytes
+        })
+        .sum()
+}
+
+/// A process and all of its descendants, found through
+/// `/proc/<pid>/task/<tid>/children`
+pub fn process_tree(pid: u32) -> Vec<u32> {
+    let mut tree = Vec::new();
+    let mut pending = vec![pid];
+
+    while let Some(pid) = pending.pop() {
+        if tree.contains(&pid) || !Path::new(&format!("/proc/{pid}")).exists() {
+            continue;
+        }
+        tree.push(pid);
+
+        let Ok(tasks) = fs::read_dir(format!("/proc/{pid}/task")) else {
+            continue;
+        };
+        for task in tasks.flatten() {
+            if let Ok(children) = fs::read_to_string(task.path().join("children")) {
+                pending.extend(
+                    children
+                        .split_whitespace()
+                        .filter_map(|c| c.parse::<u32>().ok()),
+                );
+            }
+        }
+    }
+
+    tree
+}
+
+/// Parse thread and context switch counts from `/proc/<pid>/status`
+pub fn parse_status(contents: &str, stats: &mut ProcStats) {
+    for line in contents.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim().parse::<u64>().unwrap_or(0);
+        match key {
+            "Threads" => stats.threads = value,
+            "voluntary_ctxt_switches" => stats.voluntary_ctxt_switches = value,
+            "nonvoluntary_ctxt_switches" => stats.involuntary_ctxt_switches = value,
+            _ => {}
+        }
+    }
+}
+
+/// Set the context switch counts to their totals over the
+/// `/proc/<pid>/task/<tid>/status` of each thread of a process
+pub fn parse_task_statuses<'a>(statuses: impl IntoIterator<Item = &'a str>, stats: &mut ProcStats) {
+    let mut voluntary = 0;
+    let mut involuntary = 0;
+    for contents in statuses {
+        let mut task = ProcStats::default();
+        parse_status(contents, &mut task);
+        voluntary += task.voluntary_ctxt_switches;
+        involuntary += task.involuntary_ctxt_switches;
+    }
+    stats.voluntary_ctxt_switches = voluntary;
+    stats.involuntary_ctxt_switches = involuntary;
+}
+
+/// Parse the anon/file/shmem resident memory split from `/proc/<pid>/smaps_rollup`
+///
+/// Uses the proportional `Pss_*` fields so that pages shared between processes
+/// in the same tree are not counted twice when summed.
+pub fn parse_smaps_rollup(contents: &str, stats: &mut ProcStats) {
+    for line in contents.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let kib = value
+            .trim()
+            .trim_end_matches("kB")
+            .trim()
+            .parse::<u64>()
+            .unwrap_or(0);
+        match key {
+            "Pss_Anon" => stats.rss_anon = kib * 1024,
+            "Pss_File" => stats.rss_file = kib * 1024,
+            "Pss_Shmem" => stats.rss_shmem = kib * 1024,
+            _ => {}
+        }
+    }
+}
+
+/// Parse storage-layer I/O counters from `/proc/<pid>/io`
+pub fn parse_io(contents: &str, stats: &mut ProcStats) {
+    for line in contents.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim().parse::<u64>().unwrap_or(0);
+        match key {
+            "read_bytes" => stats.read_bytes = value,
+            "write_bytes" => stats.write_bytes = value,
+            "cancelled_write_bytes" => stats.cancelled_write_bytes = value,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stat() {
+        let contents = "1234 (b-msghand (x)) S 1 1234 1234 0 -1 4194560 5000 0 12 0 \
+                        700 300 0 0 20 0 8 0 100 1000000 500 18446744073709551615";
+        let fields = parse_stat(contents).unwrap();
+        assert_eq!(fields.minor_faults, 5000);
+        assert_eq!(fields.major_faults, 12);
+        assert_eq!(fields.utime, 700);
+        assert_eq!(fields.stime, 300);
+
+        assert!(parse_stat("garbage").is_none());
+    }
+
+    #[test]
+    fn test_process_tree() {
+        let mut child = std::process::Command::new("sh")
+            .args(["-c", "sleep 5 & wait"])
+            .spawn()
+            .unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tree = process_tree(child.id());
+        for pid in tree.iter().rev() {
+            unsafe {
+                libc::kill(*pid as i32, libc::SIGKILL);
+            }
+        }
+        child.wait().unwrap();
+
+        assert_eq!(tree[0], child.id());
+        assert_eq!(tree.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_status_and_io() {
+        let mut stats = ProcStats::default();
+        parse_status(
+            "Name:\tbitcoind\nThreads:\t17\nvoluntary_ctxt_switches:\t150\nnonvoluntary_ctxt_switches:\t42\n",
+            &mut stats,
+        );
+        parse_io(
+            "rchar: 1\nwchar: 2\nread_bytes: 4096\nwrite_bytes: 8192\ncancelled_write_bytes: 512\n",
+            &mut stats,
+        );
+        parse_smaps_rollup(
+            "Rss:  1420 kB\nPss_Anon:  104 kB\nPss_File:  376 kB\nPss_Shmem:  2 kB\n",
+            &mut stats,
+        );
+
+        assert_eq!(stats.threads, 17);
+        assert_eq!(stats.voluntary_ctxt_switches, 150);
+        assert_eq!(stats.involuntary_ctxt_switches, 42);
+        assert_eq!(stats.read_bytes, 4096);
+        assert_eq!(stats.write_bytes, 8192);
+        assert_eq!(stats.cancelled_write_bytes, 512);
+        assert_eq!(stats.rss_anon, 104 * 1024);
+        assert_eq!(stats.rss_file, 376 * 1024);
+        assert_eq!(stats.rss_shmem, 2 * 1024);
+    }
+
+    #[test]
+    fn test_parse_task_statuses() {
+        let task = |name: &str, voluntary: u64, involuntary: u64| {
+            format!(
+                "Name:\t{name}\nThreads:\t3\nvoluntary_ctxt_switches:\t{voluntary}\n\
+                 nonvoluntary_ctxt_switches:\t{involuntary}\n"
+            )
+        };
+        let statuses = [
+            task("bitcoind", 10, 1),
+            task("b-msghand", 5000, 300),
+            task("b-scriptch.0", 2500, 700),
+        ];
+
+        let mut stats = ProcStats::default();
+        parse_status(&statuses[0], &mut stats);
+        parse_task_statuses(statuses.iter().map(String::as_str), &mut stats);
+        assert_eq!(stats.threads, 3);
+        assert_eq!(stats.voluntary_ctxt_switches, 7510);
+        assert_eq!(stats.involuntary_ctxt_switches, 1001);
+    }
+}
@@ -1,16 +1,22 @@
 use anyhow::Result;
-use libc;
 use log::{debug, info, trace, warn};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use sysinfo::{Pid, System};
 
+use crate::benchmarks::host_monitor::{
+    HostMonitor, HostSample, InterferenceSummary, DEFAULT_INTERFERENCE_THRESHOLD,
+};
+use crate::benchmarks::procfs::ProcStats;
+use crate::benchmarks::utils::get_datadir_sizes;
+
 /// Data collected during a single profiling sample point
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ProfileSample {
     /// Seconds elapsed since start of profiling
     pub time: u64,
@@ -24,6 +30,80 @@ pub struct ProfileSample {
     pub disk_read: u64,
     /// Total disk write in bytes
     pub disk_write: u64,
+    /// Total voluntary context switches
+    pub voluntary_ctxt_switches: u64,
+    /// Total involuntary context switches
+    pub involuntary_ctxt_switches: u64,
+    /// Total minor page faults
+    pub minor_faults: u64,
+    /// Total major page faults
+    pub major_faults: u64,
+    /// Total number of threads
+    pub threads: u64,
+    /// Total number of open file descriptors
+    pub open_fds: u64,
+    /// Anonymous resident memory in bytes (from smaps_rollup)
+    pub rss_anon: u64,
+    /// File-backed resident memory in bytes (from smaps_rollup)
+    pub rss_file: u64,
+    /// Shared memory resident in bytes (from smaps_rollup)
+    pub rss_shmem: u64,
+    /// Cumulative bytes read from storage (`read_bytes` in /proc/<pid>/io)
+    pub io_read_bytes: u64,
+    /// Cumulative bytes written to storage (`write_bytes` in /proc/<pid>/io)
+    pub io_write_bytes: u64,
+    /// Cumulative cancelled write bytes (`cancelled_write_bytes` in /proc/<pid>/io)
+    pub io_cancelled_write_bytes: u64,
+    /// Size in bytes of each tracked datadir subdirectory
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub datadir_sizes: BTreeMap<String, u64>,
+    /// Host-wide contention signals
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<HostSample>,
+}
+
+impl ProfileSample {
+    /// Named values of the process metrics in this sample, for aggregating
+    /// across runs. Datadir sizes are named `size_<dir>`.
+    pub fn metrics(&self) -> Vec<(String, f64)> {
+        let mut metrics: Vec<(String, f64)> = [
+            ("cpu_usage", self.cpu_usage as f64),
+            ("memory", self.memory as f64),
+            ("virtual_memory", self.virtual_memory as f64),
+            ("disk_read", self.disk_read as f64),
+            ("disk_write", self.disk_write as f64),
+            (
+                "voluntary_ctxt_switches",
+                self.voluntary_ctxt_switches as f64,
+            ),
+            (
+                "involuntary_ctxt_switches",
+                self.involuntary_ctxt_switches as f64,
+            ),
+            ("minor_faults", self.minor_faults as f64),
+            ("major_faults", self.major_faults as f64),
+            ("threads", self.threads as f64),
+            ("open_fds", self.open_fds as f64),
+            ("rss_anon", self.rss_anon as f64),
+            ("rss_file", self.rss_file as f64),
+            ("rss_shmem", self.rss_shmem as f64),
+            ("io_read_bytes", self.io_read_bytes as f64),
+            ("io_write_bytes", self.io_write_bytes as f64),
+            (
+                "io_cancelled_write_bytes",
+                self.io_cancelled_write_bytes as f64,
+            ),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect();
+        metrics.extend(
+            self.datadir_sizes
+                .iter()
+                .map(|(dir, size)| (format!("size_{dir}"), *size as f64)),
+        );
+        metrics
+    }
 }
 
 /// Results from a profiling session
@@ -35,8 +115,15 @@ pub struct ProfileResult {
     pub duration: f64,
     /// Process exit code
     pub exit_code: i32,
+    /// Wall-clock time profiling started, in seconds since the Unix epoch,
+    /// used to line samples up with debug.log
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<f64>,
     /// Samples collected during profiling
     pub samples: Vec<ProfileSample>,
+    /// Summary of contention from other load on the host
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interference: Option<InterferenceSummary>,
 }
 
 /// Builder for Profiler
@@ -49,6 +136,14 @@ pub struct ProfilerBuilder {
     benchmark_cores: Option<String>,
     /// Custom output file name (defaults to "profile_data.json")
     output_filename: Option<String>,
+    /// Network datadir whose subdirectories are sampled for size
+    datadir: Option<PathBuf>,
+    /// Subdirectories of the datadir to track
+    datadir_dirs: Vec<String>,
+    /// Datadir whose backing device is sampled for host contention
+    host_datadir: Option<PathBuf>,
+    /// Foreign CPU share (percent) above which a run is flagged
+    interference_threshold: f64,
 }
 
 impl ProfilerBuilder {
@@ -59,6 +154,10 @@ impl ProfilerBuilder {
             sample_interval: 5, // Default 5 second interval
             benchmark_cores: None,
             output_filename: None,
+            datadir: None,
+            datadir_dirs: Vec::new(),
+            host_datadir: None,
+            interference_threshold: DEFAULT_INTERFERENCE_THRESHOLD,
         }
     }
 
@@ -80,6 +179,24 @@ impl ProfilerBuilder {
         self
     }
 
+    /// Track the size of the given subdirectories of a network datadir
+    pub fn datadir_dirs(mut self, datadir: &Path, dirs: Vec<String>) -> Self {
+        self.datadir = Some(datadir.to_path_buf());
+        self.datadir_dirs = dirs;
+        self
+    }
+
+    /// Sample host-wide contention, using the device backing `datadir` for
+    /// disk statistics and flagging runs above `threshold` percent foreign
+    /// CPU load on the benchmark cores
+    pub fn host_monitoring(mut self, datadir: &Path, threshold: Option<f64>) -> Self {
+        self.host_datadir = Some(datadir.to_path_buf());
+        if let Some(threshold) = threshold {
+            self.interference_threshold = threshold;
+        }
+        self
+    }
+
     /// Build the Profiler instance
     pub fn build(self) -> Result<Profiler> {
         // Create the output directory if it doesn't exist
@@ -96,6 +213,11 @@ impl ProfilerBuilder {
         Ok(Profiler {
             output_path,
             sample_interval: self.sample_interval,
+            datadir: self.datadir,
+            datadir_dirs: self.datadir_dirs,
+            benchmark_cores: self.benchmark_cores,
+            host_datadir: self.host_datadir,
+            interference_threshold: self.interference_threshold,
         })
     }
 }
@@ -106,6 +228,16 @@ pub struct Profiler {
     output_path: PathBuf,
     /// Sample interval in seconds
     sample_interval: u64,
+    /// Network datadir whose subdirectories are sampled for size
+    datadir: Option<PathBuf>,
+    /// Subdirectories of the datadir to track
+    datadir_dirs: Vec<String>,
+    /// CPU cores the profiled process is bound to
+    benchmark_cores: Option<String>,
+    /// Datadir whose backing device is sampled for host contention
+    host_datadir: Option<PathBuf>,
+    /// Foreign CPU share (percent) above which a run is flagged
+    interference_threshold: f64,
 }
 
 impl Profiler {
@@ -114,7 +246,34 @@ impl Profiler {
         ProfilerBuilder::new(output_dir)
     }
 
-    /// Profile an already launched child process
+    /// Interval between samples
+    pub fn sample_interval(&self) -> Duration {
+        Duration::from_secs(self.sample_interval)
+    }
+
+    /// Start profiling an already launched process
+    ///
+    /// The caller drives sampling through the returned session, which allows
+    /// profiling to be combined with other monitoring of the same process.
+    pub fn start(&self, pid: u32) -> Result<ProfileSession<'_>> {
+        debug!("Profiling process with PID: {pid}");
+        let host_monitor = match &self.host_datadir {
+            Some(datadir) => Some(HostMonitor::new(self.benchmark_cores.as_deref(), datadir)?),
+            None => None,
+        };
+
+        Ok(ProfileSession {
+            profiler: self,
+            parent_pid: Pid::from_u32(pid),
+            start_time: Instant::now(),
+            start_wall_time: SystemTime::now(),
+            samples: Vec::new(),
+            sys: System::new_all(),
+            host_monitor,
+        })
+    }
+
+    /// Profile an already launched child process until it exits
     /// This allows the caller to handle process launching and CPU affinity
     pub fn profile_process(
         &mut self,
@@ -124,91 +283,17 @@ impl Profiler {
         info!("Profiling process from command: {command}");
         debug!("Will sample every {} seconds", self.sample_interval);
 
-        let parent_pid = Pid::from_u32(child.id());
-        debug!("Profiling process with PID: {}", parent_pid.as_u32());
-
-        let start_time = Instant::now();
-        let mut samples = Vec::new();
-        let mut sys = System::new_all();
-
-        // Main profiling loop with timeout guard for bitcoind stalling
-        let mut last_active_time = Instant::now();
-        const MAX_INACTIVE_DURATION: Duration = Duration::from_secs(300); // 5 minutes timeout
-
+        let mut session = self.start(child.id())?;
         while child.try_wait()?.is_none() {
-            // Refresh system info to get latest process data
-            sys.refresh_all();
-
-            // Check if the process is still running
-            if sys.process(parent_pid).is_none() {
+            if !session.sample() {
                 debug!("Process appears to have terminated outside our monitoring");
                 break;
             }
-
-            // Collect sample data
-            let sample = collect_process_sample(&sys, parent_pid, start_time.elapsed().as_secs());
-
-            trace!(
-                "Sample at {}s: CPU: {:.2}%, Memory: {:.2}MB, VMemory: {:.2}MB, Disk R/W: {}/{} bytes",
-                sample.time,
-                sample.cpu_usage,
-                sample.memory as f64 / (1024.0 * 1024.0),
-                sample.virtual_memory as f64 / (1024.0 * 1024.0),
-                sample.disk_read,
-                sample.disk_write
-            );
-
-            // If we detect activity, update the last active timestamp
-            if sample.cpu_usage > 0.5 || sample.disk_read > 0 || sample.disk_write > 0 {
-                last_active_time = Instant::now();
-            }
-
-            // Check for potential stalling - if no activity for MAX_INACTIVE_DURATION,
-            // terminate the process
-            if Instant::now().duration_since(last_active_time) > MAX_INACTIVE_DURATION {
-                warn!("Process seems to be stalled (no activity for 5 minutes). Terminating.");
-
-                // Try to terminate gracefully first
-                if let Err(e) = child.kill() {
-                    warn!("Failed to kill stalled process: {e}");
-                    // Try direct kill via system call as fallback
-                    unsafe {
-                        libc::kill(parent_pid.as_u32() as i32, libc::SIGTERM);
-                    }
-                }
-
-                // Also try to terminate any child processes
-                let pgid = -(parent_pid.as_u32() as i32);
-                unsafe {
-                    // Send SIGTERM to the process group
-                    libc::kill(pgid, libc::SIGTERM);
-                }
-
-                break;
-            }
-
-            samples.push(sample);
-            std::thread::sleep(Duration::from_secs(self.sample_interval));
+            std::thread::sleep(self.sample_interval());
         }
 
         let exit_status = child.wait()?;
-        let duration = start_time.elapsed().as_secs_f64();
-        let exit_code = exit_status.code().unwrap_or(-1);
-        let profile_result = ProfileResult {
-            command: command.to_string(),
-            duration,
-            exit_code,
-            samples,
-        };
-
-        export_json(&profile_result, &self.output_path)?;
-        export_csv(&profile_result, &self.output_path.with_extension("csv"))?;
-
-        debug!(
-            "Profiling completed with {} samples collected",
-            profile_result.samples.len()
-        );
-        Ok(profile_result)
+        session.finish(command, exit_status.code().unwrap_or(-1))
     }
 
     /// Backward compatibility method that spawns a command and profiles it
@@ -230,18 +315,106 @@ impl Profiler {
     }
 }
 
-/// Collect a sample for a process and all its children
-fn collect_process_sample(sys: &System, parent_pid: Pid, elapsed_seconds: u64) -> ProfileSample {
-    let mut all_pids = Vec::new();
-    get_all_related_pids(sys, parent_pid, &mut all_pids);
+/// An in-progress profile of a running process
+pub struct ProfileSession<'a> {
+    profiler: &'a Profiler,
+    parent_pid: Pid,
+    start_time: Instant,
+    start_wall_time: SystemTime,
+    samples: Vec<ProfileSample>,
+    sys: System,
+    host_monitor: Option<HostMonitor>,
+}
+
+impl ProfileSession<'_> {
+    /// Take a sample of the process tree, returning false if the process is
+    /// no longer running
+    pub fn sample(&mut self) -> bool {
+        // Refresh system info to get latest process data
+        self.sys.refresh_all();
+        if self.sys.process(self.parent_pid).is_none() {
+            return false;
+        }
+
+        let mut all_pids = Vec::new();
+        get_all_related_pids(&self.sys, self.parent_pid, &mut all_pids);
+        let mut sample =
+            collect_process_sample(&self.sys, &all_pids, self.start_time.elapsed().as_secs());
+        if let Some(datadir) = &self.profiler.datadir {
+            sample.datadir_sizes = get_datadir_sizes(datadir, &self.profiler.datadir_dirs);
+        }
+        if let Some(monitor) = self.host_monitor.as_mut() {
+            sample.host = Some(monitor.sample(&process_pids(&self.sys, &all_pids)));
+        }
+
+        trace!(
+            "Sample at {}s: CPU: {:.2}%, Memory: {:.2}MB, VMemory: {:.2}MB, Disk R/W: {}/{} bytes",
+            sample.time,
+            sample.cpu_usage,
+            sample.memory as f64 / (1024.0 * 1024.0),
+            sample.virtual_memory as f64 / (1024.0 * 1024.0),
+            sample.disk_read,
+            sample.disk_write
+        );
+
+        self.samples.push(sample);
+        true
+    }
+
+    /// Finish profiling and export the results
+    pub fn finish(self, command: &str, exit_code: i32) -> Result<ProfileResult> {
+        let duration = self.start_time.elapsed().as_secs_f64();
+        let host_samples: Vec<&HostSample> = self
+            .samples
+            .iter()
+            .filter_map(|s| s.host.as_ref())
+            .collect();
+        let interference =
+            InterferenceSummary::from_samples(&host_samples, self.profiler.interference_threshold);
+        if let Some(summary) = interference.as_ref().filter(|s| s.flagged) {
+            warn!(
+                "Run was affected by other load on the host: {:.1}% mean foreign CPU on benchmark cores (threshold {:.1}%)",
+                summary.mean_foreign_cpu, summary.threshold
+            );
+        }
+        let profile_result = ProfileResult {
+            command: command.to_string(),
+            duration,
+            exit_code,
+            start_time: self
+                .start_wall_time
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|d| d.as_secs_f64()),
+            samples: self.samples,
+            interference,
+        };
+
+        export_json(&profile_result, &self.profiler.output_path)?;
+        export_csv(
+            &profile_result,
+            &self.profiler.datadir_dirs,
+            &self.profiler.output_path.with_extension("csv"),
+        )?;
+
+        debug!(
+            "Profiling completed with {} samples collected",
+            profile_result.samples.len()
+        );
+        Ok(profile_result)
+    }
+}
 
+/// Collect a sample for a process and all its children
+fn collect_process_sample(sys: &System, all_pids: &[Pid], elapsed_seconds: u64) -> ProfileSample {
     let mut total_cpu = 0.0;
     let mut total_memory = 0;
     let mut total_virtual_memory = 0;
     let mut total_disk_read = 0;
     let mut total_disk_write = 0;
+    let mut proc_stats = ProcStats::default();
 
-    for &pid in &all_pids {
+    for &pid in all_pids {
         if let Some(process) = sys.process(pid) {
             total_cpu += process.cpu_usage();
             total_memory += process.memory();
@@ -249,6 +422,12 @@ fn collect_process_sample(sys: &System, parent_pid: Pid, elapsed_seconds: u64) -
             let disk_usage = process.disk_usage();
             total_disk_read += disk_usage.read_bytes;
             total_disk_write += disk_usage.written_bytes;
+
+            // Threads are reported as processes too, but their counters are
+            // already included in the owning process
+            if process.thread_kind().is_none() {
+                proc_stats += &ProcStats::read(pid.as_u32());
+            }
         }
     }
 
@@ -259,9 +438,35 @@ fn collect_process_sample(sys: &System, parent_pid: Pid, elapsed_seconds: u64) -
         virtual_memory: total_virtual_memory,
         disk_read: total_disk_read,
         disk_write: total_disk_write,
+        voluntary_ctxt_switches: proc_stats.voluntary_ctxt_switches,
+        involuntary_ctxt_switches: proc_stats.involuntary_ctxt_switches,
+        minor_faults: proc_stats.minor_faults,
+        major_faults: proc_stats.major_faults,
+        threads: proc_stats.threads,
+        open_fds: proc_stats.open_fds,
+        rss_anon: proc_stats.rss_anon,
+        rss_file: proc_stats.rss_file,
+        rss_shmem: proc_stats.rss_shmem,
+        io_read_bytes: proc_stats.read_bytes,
+        io_write_bytes: proc_stats.write_bytes,
+        io_cancelled_write_bytes: proc_stats.cancelled_write_bytes,
+        datadir_sizes: BTreeMap::new(),
+        host: None,
     }
 }
 
+/// Filter a process tree down to processes, dropping threads
+fn process_pids(sys: &System, all_pids: &[Pid]) -> Vec<u32> {
+    all_pids
+        .iter()
+        .filter(|pid| {
+            sys.process(**pid)
+                .is_some_and(|process| process.thread_kind().is_none())
+        })
+        .map(|pid| pid.as_u32())
+        .collect()
+}
+
 /// Recursively collect process tree PIDs
 fn get_all_related_pids(sys: &System, parent_pid: Pid, result: &mut Vec<Pid>) {
     // Check if the parent_pid exists in the system before adding it
@@ -300,21 +505,69 @@ fn export_json(result: &ProfileResult, path: &Path) -> Result<()> {
 }
 
 /// Export profile results to CSV
-fn export_csv(result: &ProfileResult, path: &Path) -> Result<()> {
+fn export_csv(result: &ProfileResult, datadir_dirs: &[String], path: &Path) -> Result<()> {
     let mut file = File::create(path)?;
-    writeln!(file, "time,cpu,memory,virtual_memory,disk_read,disk_write")?;
+    write!(
+        file,
+        "time,cpu,memory,virtual_memory,disk_read,disk_write,\
+         voluntary_ctxt_switches,involuntary_ctxt_switches,minor_faults,major_faults,\
+         threads,open_fds,rss_anon,rss_file,rss_shmem,\
+         io_read_bytes,io_write_bytes,io_cancelled_write_bytes,\
+         psi_cpu_some,psi_io_some,psi_io_full,psi_memory_some,psi_memory_full,foreign_cpu,\
+         disk_reads,disk_writes,disk_sectors_read,disk_sectors_written,disk_busy_ms"
+    )?;
+    for dir in datadir_dirs {
+        write!(file, ",size_{dir}")?;
+    }
+    writeln!(file)?;
 
     for sample in &result.samples {
-        writeln!(
+        write!(
             file,
-            "{},{},{},{},{},{}",
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
             sample.time,
             sample.cpu_usage,
             sample.memory,
             sample.virtual_memory,
             sample.disk_read,
-            sample.disk_write
+            sample.disk_write,
+            sample.voluntary_ctxt_switches,
+            sample.involuntary_ctxt_switches,
+            sample.minor_faults,
+            sample.major_faults,
+            sample.threads,
+            sample.open_fds,
+            sample.rss_anon,
+            sample.rss_file,
+            sample.rss_shmem,
+            sample.io_read_bytes,
+            sample.io_write_bytes,
+            sample.io_cancelled_write_bytes
         )?;
+        let host = sample.host.clone().unwrap_or_default();
+        write!(
+            file,
+            ",{},{},{},{},{},{:.2},{},{},{},{},{}",
+            host.psi_cpu_some,
+            host.psi_io_some,
+            host.psi_io_full,
+            host.psi_memory_some,
+            host.psi_memory_full,
+            host.foreign_cpu,
+            host.disk_reads,
+            host.disk_writes,
+            host.disk_sectors_read,
+            host.disk_sectors_written,
+            host.disk_busy_ms
+        )?;
+        for dir in datadir_dirs {
+            write!(
+                file,
+                ",{}",
+                sample.datadir_sizes.get(dir).copied().unwrap_or_default()
+            )?;
+        }
+        writeln!(file)?;
     }
 
     Ok(())
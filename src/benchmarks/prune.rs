@@ -0,0 +1,371 @@
+use anyhow::{Context, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::benchmarks::hooks::HookMode;
+
+/// Smallest prune target bitcoind accepts, in MiB
+pub const MIN_PRUNE_TARGET: u64 = 550;
+
+/// How far block and undo files may exceed the target, in MiB. bitcoind only
+/// deletes whole block files, of up to 128 MiB, with their undo files, and
+/// never those holding the last 288 blocks, so it can stay about a file over
+/// the target.
+const PRUNE_SLACK: u64 = 144;
+
+/// How often the size of the blocks directory is sampled
+pub const BLOCKS_SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
+const MIB: u64 = 1024 * 1024;
+
+/// Configuration of pruned mode
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PruneConfig {
+    /// Prune target in MiB. May contain `{var}` placeholders, which are
+    /// filled in from the parameter matrix.
+    pub target: String,
+    /// How each run is prepared, "assumeutxo" (the default) or "full_ibd"
+    #[serde(default)]
+    pub prepare: Option<String>,
+}
+
+impl PruneConfig {
+    /// Check the preparation mode, and the target if it does not depend on
+    /// parameters
+    pub fn validate(&self) -> Result<()> {
+        self.prepare_mode()?;
+        if !self.target.contains('{') {
+            parse_target(&self.target)?;
+        }
+        Ok(())
+    }
+
+    /// Mode whose prepare hook sets up each run
+    pub fn prepare_mode(&self) -> Result<HookMode> {
+        match self
+            .prepare
+            .as_deref()
+            .map(HookMode::mode_from_str)
+            .transpose()?
+        {
+            None | Some(HookMode::AssumeUtxo) => Ok(HookMode::AssumeUtxo),
+            Some(HookMode::FullIbd) => Ok(HookMode::FullIbd),
+            Some(mode) => anyhow::bail!("Pruned mode cannot prepare runs with {mode:?} mode"),
+        }
+    }
+
+    /// Fill in `{var}` placeholders from `params` and parse the target
+    pub fn resolve(&self, params: &HashMap<String, String>) -> Result<u64> {
+        let mut target = self.target.clone();
+        for (var, value) in params {
+            target = target.replace(&format!("{{{var}}}"), value);
+        }
+        parse_target(&target)
+    }
+}
+
+fn parse_target(target: &str) -> Result<u64> {
+    let mib = target
+        .trim()
+        .parse::<u64>()
+        .with_context(|| format!("Invalid prune target: {target}"))?;
+    if mib < MIN_PRUNE_TARGET {
+        anyhow::bail!("Prune target must be at least {MIN_PRUNE_TARGET} MiB, got {mib}");
+    }
+    Ok(mib)
+}
+
+/// Size of the block and undo files at a point in the run
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BlocksSample {
+    /// Seconds since the run started
+    pub time: f64,
+    /// Size in bytes
+    pub size: u64,
+}
+
+/// How a pruned run used its block storage
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PruneResult {
+    /// Prune target in MiB
+    pub target: u64,
+    /// Size in bytes of the block and undo files at the end of the run
+    pub block_files_size: u64,
+    /// Whether any block file below the last one had been deleted
+    pub pruned: bool,
+    /// Size of the block and undo files over the run
+    pub samples: Vec<BlocksSample>,
+}
+
+impl PruneResult {
+    /// Inspect the block files a run left in `blocks_dir`
+    pub fn inspect(target: u64, blocks_dir: &Path, samples: Vec<BlocksSample>) -> Result<Self> {
+        let files = BlockFiles::read(blocks_dir)?;
+        // Block files are numbered from 0 and only deleted by pruning. The
+        // first may be kept, as in assumeutxo mode, where it holds the blocks
+        // below the snapshot, which its chainstate never prunes.
+        let pruned = files
+            .numbers
+            .iter()
+            .max()
+            .is_some_and(|&last| files.numbers.len() <= last as usize);
+
+        Ok(Self {
+            target,
+            block_files_size: files.size,
+            pruned,
+            samples,
+        })
+    }
+
+    /// Largest size of the block and undo files seen over the run, in bytes
+    fn peak_size(&self) -> u64 {
+        self.samples
+            .iter()
+            .map(|sample| sample.size)
+            .fold(self.block_files_size, u64::max)
+    }
+
+    /// Why the run did not prune as expected, if it did not. A node only
+    /// has to prune once its block files grow past the target.
+    pub fn failure(&self) -> Option<String> {
+        let size = self.block_files_size / MIB;
+        let limit = self.target + PRUNE_SLACK;
+        if !self.pruned && self.peak_size() / MIB > limit {
+            Some(format!(
+                "Node did not prune, block files reached {} MiB with a target of {} MiB",
+                self.peak_size() / MIB,
+                self.target
+            ))
+        } else if size > limit {
+            Some(format!(
+                "Block files are {size} MiB, over the prune target of {} MiB",
+                self.target
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+/// Block and undo files in a blocks directory
+struct BlockFiles {
+    /// Total size in bytes
+    size: u64,
+    /// Numbers of the `blk` files
+    numbers: Vec<u32>,
+}
+
+impl BlockFiles {
+    fn read(blocks_dir: &Path) -> Result<Self> {
+        let mut files = Self {
+            size: 0,
+            numbers: Vec::new(),
+        };
+        for entry in fs::read_dir(blocks_dir)
+            .with_context(|| format!("Failed to read {}", blocks_dir.display()))?
+        {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Some(stem) = name.strip_suffix(".dat") else {
+                continue;
+            };
+            if let Some(number) = stem.strip_prefix("blk") {
+                files.numbers.extend(number.parse::<u32>().ok());
+            } else if !stem.starts_with("rev") {
+                continue;
+            }
+            // A file pruned while sampling no longer counts
+            files.size += entry.metadata().map_or(0, |metadata| metadata.len());
+        }
+        Ok(files)
+    }
+}
+
+/// Size of the block and undo files, or 0 before the directory exists
+fn block_files_size(blocks_dir: &Path) -> u64 {
+    BlockFiles::read(blocks_dir).map_or(0, |files| files.size)
+}
+
+/// Samples the size of the block and undo files in the background
+pub struct BlocksMonitor {
+    stop: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<Vec<BlocksSample>>>,
+}
+
+impl BlocksMonitor {
+    /// Start sampling the size of the block and undo files in `blocks_dir`
+    /// every `interval`
+    pub fn start(blocks_dir: PathBuf, interval: Duration) -> Self {
+        let start = Instant::now();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = Arc::clone(&stop);
+
+        let handle = thread::spawn(move || {
+            let mut samples = Vec::new();
+            loop {
+                let next = Instant::now() + interval;
+                samples.push(BlocksSample {
+                    time: start.elapsed().as_secs_f64(),
+                    size: block_files_size(&blocks_dir),
+                });
+                while Instant::now() < next && !thread_stop.load(Ordering::SeqCst) {
+                    thread::sleep(Duration::from_millis(100));
+                }
+                // Always end with the size at the end of the run
+                if thread_stop.load(Ordering::SeqCst) {
+                    samples.push(BlocksSample {
+                        time: start.elapsed().as_secs_f64(),
+                        size: block_files_size(&blocks_dir),
+                    });
+                    return samples;
+                }
+            }
+        });
+
+        Self {
+            stop,
+            handle: Some(handle),
+        }
+    }
+
+    /// Stop sampling and return the samples taken
+    pub fn finish(mut self) -> Result<Vec<BlocksSample>> {
+        self.stop.store(true, Ordering::SeqCst);
+        let samples = self
+            .handle
+            .take()
+            .context("Blocks monitor already finished")?
+            .join()
+            .map_err(|_| anyhow::anyhow!("Blocks monitor thread panicked"))?;
+        info!("Took {} samples of the block files size", samples.len());
+        Ok(samples)
+    }
+}
+
+impl Drop for BlocksMonitor {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_file(dir: &Path, name: &str, mib: u64) {
+        fs::File::create(dir.join(name))
+            .unwrap()
+            .set_len(mib * MIB)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_prune_config() {
+        let config = PruneConfig {
+            target: "{prune}".to_string(),
+            prepare: Some("full_ibd".to_string()),
+        };
+        config.validate().unwrap();
+        assert_eq!(config.prepare_mode().unwrap(), HookMode::FullIbd);
+        let params = HashMap::from([("prune".to_string(), "1000".to_string())]);
+        assert_eq!(config.resolve(&params).unwrap(), 1000);
+        assert!(config.resolve(&HashMap::new()).is_err());
+
+        let too_small = PruneConfig {
+            target: "100".to_string(),
+            prepare: None,
+        };
+        assert!(too_small.validate().is_err());
+        let reindex = PruneConfig {
+            target: "550".to_string(),
+            prepare: Some("reindex".to_string()),
+        };
+        assert!(reindex.validate().is_err());
+    }
+
+    #[test]
+    fn test_prune_result() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = |name: &str, mib: u64| create_file(dir.path(), name, mib);
+        let inspect = |target: u64, samples: &[u64]| {
+            let samples = samples
+                .iter()
+                .map(|&mib| BlocksSample {
+                    time: 0.0,
+                    size: mib * MIB,
+                })
+                .collect();
+            PruneResult::inspect(target, dir.path(), samples).unwrap()
+        };
+        file("blk00003.dat", 300);
+        file("rev00003.dat", 40);
+        file("blk00004.dat", 200);
+        fs::create_dir(dir.path().join("index")).unwrap();
+
+        let result = inspect(550, &[100, 700]);
+        assert!(result.pruned);
+        assert_eq!(result.block_files_size, 540 * MIB);
+        assert_eq!(result.failure(), None);
+
+        file("blk00005.dat", 200);
+        let over = inspect(550, &[]);
+        assert!(over.failure().unwrap().contains("over the prune target"));
+    }
+
+    #[test]
+    fn test_prune_result_unpruned() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = |name: &str, mib: u64| create_file(dir.path(), name, mib);
+        let sample = |mib: u64| BlocksSample {
+            time: 0.0,
+            size: mib * MIB,
+        };
+        file("blk00000.dat", 128);
+        file("rev00000.dat", 20);
+        file("blk00001.dat", 128);
+
+        // A run whose block files never outgrew the target had nothing to
+        // prune
+        let small = PruneResult::inspect(550, dir.path(), vec![sample(100)]).unwrap();
+        assert!(!small.pruned);
+        assert_eq!(small.failure(), None);
+
+        // One that went over it without deleting anything did not prune
+        let unpruned = PruneResult::inspect(550, dir.path(), vec![sample(800)]).unwrap();
+        assert!(unpruned.failure().unwrap().contains("did not prune"));
+    }
+
+    #[test]
+    fn test_prune_result_assumeutxo() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = |name: &str, mib: u64| create_file(dir.path(), name, mib);
+        // The first block file holds the blocks below the snapshot, which
+        // are never pruned, while those after it were
+        file("blk00000.dat", 1);
+        file("rev00000.dat", 1);
+        file("blk00006.dat", 128);
+        file("rev00006.dat", 20);
+        file("blk00007.dat", 128);
+        file("blk00008.dat", 64);
+
+        let samples = vec![BlocksSample {
+            time: 30.0,
+            size: 700 * MIB,
+        }];
+        let result = PruneResult::inspect(550, dir.path(), samples).unwrap();
+        assert!(result.pruned);
+        assert_eq!(result.failure(), None);
+    }
+}
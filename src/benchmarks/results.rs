@@ -1,7 +1,12 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
+use crate::benchmarks::chain_state::ChainState;
+use crate::benchmarks::hook_runner::HookRecord;
 use crate::benchmarks::profiler::ProfileResult;
+use crate::benchmarks::prune::PruneResult;
+use crate::benchmarks::supervisor::{ResourceUsage, StopReason};
+use crate::cgroup::{AppliedLimits, CgroupStats};
 
 /// Type of instrumentation used for a benchmark run
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -12,6 +17,36 @@ pub enum InstrumentationType {
     PerfInstrumented,
 }
 
+/// Outcome of a single benchmark run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    /// The command ran to completion
+    #[default]
+    Completed,
+    /// The command was terminated after showing no activity
+    Stalled,
+    /// The command was terminated after exceeding the run timeout
+    TimedOut,
+    /// A log rule reported a failure
+    Failed,
+}
+
+impl From<StopReason> for RunStatus {
+    fn from(reason: StopReason) -> Self {
+        match reason {
+            // Stopping on the log pattern or height is the intended end of
+            // the run
+            StopReason::Exited | StopReason::LogPattern | StopReason::Height => {
+                RunStatus::Completed
+            }
+            StopReason::Stalled => RunStatus::Stalled,
+            StopReason::TimedOut => RunStatus::TimedOut,
+            StopReason::LogFailure => RunStatus::Failed,
+        }
+    }
+}
+
 /// Results from a single benchmark run
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RunResult {
@@ -21,6 +56,15 @@ pub struct RunResult {
     pub duration_ms: f64,
     /// Exit code from the command
     pub exit_code: i32,
+    /// Whether the run completed or was cut short
+    #[serde(default)]
+    pub status: RunStatus,
+    /// Log line that failed the run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure: Option<String>,
+    /// Why the command stopped
+    #[serde(default)]
+    pub stop_reason: StopReason,
     /// Type of instrumentation used for this run
     pub instrumentation: InstrumentationType,
     /// Output from the command (optional)
@@ -29,6 +73,63 @@ pub struct RunResult {
     /// Profiling results (optional)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub profile: Option<ProfileResult>,
+    /// Final size in bytes of each tracked datadir subdirectory, recorded
+    /// before the datadir is cleared
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub datadir_sizes: BTreeMap<String, u64>,
+    /// Exact resource accounting for the whole process tree, when the run
+    /// was isolated in a cgroup
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cgroup: Option<CgroupStats>,
+    /// Resource limits the run was constrained to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limits: Option<AppliedLimits>,
+    /// CPU time, peak RSS, faults, block I/O and context switches of the
+    /// command, from `wait4`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rusage: Option<ResourceUsage>,
+    /// Other named metrics of the run, such as sync throughput from debug.log
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metrics: BTreeMap<String, f64>,
+    /// Milestone heights the run did not reach, which have no
+    /// `time_to_height_<N>_ms` metric
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub missed_milestones: Vec<u64>,
+    /// Time spent in each validation phase, from `-debug=bench` logging
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub validation_timing: BTreeMap<String, PhaseTiming>,
+    /// Tip and UTXO set the run ended on, when chain state verification is
+    /// enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chain_state: Option<ChainState>,
+    /// Block storage of the run, in pruned mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prune: Option<PruneResult>,
+    /// Timing of the prepare and conclude hooks of the run
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hooks: Vec<HookRecord>,
+}
+
+/// Time spent in a validation phase over a run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PhaseTiming {
+    /// Number of blocks the phase was logged for
+    pub blocks: usize,
+    /// Total time in milliseconds
+    pub total_ms: f64,
+    /// Distribution of the time per block in milliseconds
+    pub per_block: RunSummary,
+}
+
+impl PhaseTiming {
+    /// Summarise the time spent in the phase for each block
+    pub fn from_block_times(per_block: &[f64]) -> Self {
+        Self {
+            blocks: per_block.len(),
+            total_ms: per_block.iter().sum(),
+            per_block: ResultAnalyzer::calculate_summary_from_durations(per_block),
+        }
+    }
 }
 
 /// Statistical summary of benchmark runs
@@ -44,6 +145,10 @@ pub struct RunSummary {
     pub median: f64,
     /// Standard deviation in milliseconds
     pub std_dev: f64,
+    /// Summaries of other per-run metrics, keyed by name, in the unit of
+    /// each metric
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metrics: BTreeMap<String, RunSummary>,
 }
 
 /// Relative speed comparison between benchmark runs
@@ -77,8 +182,11 @@ pub struct BenchmarkResult {
     pub parameters: HashMap<String, String>,
     /// Results from each run
     pub runs: Vec<RunResult>,
-    /// Statistical summary
-    pub summary: RunSummary,
+    /// Statistical summary, absent when no run completed
+    pub summary: Option<RunSummary>,
+    /// Timing of the setup and cleanup hooks
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hooks: Vec<HookRecord>,
 }
 
 /// Functions for analyzing benchmark results
@@ -86,25 +194,42 @@ pub struct ResultAnalyzer;
 
 impl ResultAnalyzer {
     /// Calculate a statistical summary for benchmark run results
-    pub fn calculate_summary(results: &[RunResult]) -> RunSummary {
+    ///
+    /// Only completed runs are included; stalled runs would skew the timings.
+    /// Returns None if no run completed.
+    pub fn calculate_summary(results: &[RunResult]) -> Option<RunSummary> {
+        let results: Vec<&RunResult> = results
+            .iter()
+            .filter(|r| r.status == RunStatus::Completed)
+            .collect();
         if results.is_empty() {
-            return RunSummary {
-                min: 0.0,
-                max: 0.0,
-                mean: 0.0,
-                median: 0.0,
-                std_dev: 0.0,
-            };
+            return None;
         }
 
         // Extract durations
         let durations: Vec<f64> = results.iter().map(|r| r.duration_ms).collect();
+        let mut summary = Self::calculate_summary_from_durations(&durations);
+
+        // Summarise each metric over the runs that recorded it
+        let mut metrics: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+        for result in &results {
+            for (name, value) in result.rusage.iter().flat_map(ResourceUsage::metrics) {
+                metrics.entry(name.to_string()).or_default().push(value);
+            }
+            for (name, value) in &result.metrics {
+                metrics.entry(name.clone()).or_default().push(*value);
+            }
+        }
+        summary.metrics = metrics
+            .into_iter()
+            .map(|(name, values)| (name, Self::calculate_summary_from_durations(&values)))
+            .collect();
 
-        Self::calculate_summary_from_durations(&durations)
+        Some(summary)
     }
 
     /// Calculate statistical summary from duration values
-    fn calculate_summary_from_durations(durations: &[f64]) -> RunSummary {
+    pub(crate) fn calculate_summary_from_durations(durations: &[f64]) -> RunSummary {
         // Calculate min and max
         let min = *durations
             .iter()
@@ -145,11 +270,18 @@ impl ResultAnalyzer {
             mean,
             median,
             std_dev,
+            metrics: BTreeMap::new(),
         }
     }
 
     /// Calculate a master summary for a set of benchmark results
+    ///
+    /// Results without a summary, where no run completed, are left out.
     pub fn calculate_master_summary(results: &[BenchmarkResult]) -> Option<MasterSummary> {
+        let results: Vec<(&BenchmarkResult, &RunSummary)> = results
+            .iter()
+            .filter_map(|result| Some((result, result.summary.as_ref()?)))
+            .collect();
         if results.is_empty() {
             return None;
         }
@@ -158,21 +290,20 @@ impl ResultAnalyzer {
         let fastest_idx = results
             .iter()
             .enumerate()
-            .min_by(|(_, a), (_, b)| {
-                a.summary
-                    .mean
-                    .partial_cmp(&b.summary.mean)
+            .min_by(|(_, (_, a)), (_, (_, b))| {
+                a.mean
+                    .partial_cmp(&b.mean)
                     .unwrap_or(std::cmp::Ordering::Equal)
             })
             .map(|(idx, _)| idx)
             .unwrap_or(0);
 
-        let fastest = &results[fastest_idx];
-        let fastest_mean = fastest.summary.mean;
+        let (fastest, fastest_summary) = results[fastest_idx];
+        let fastest_mean = fastest_summary.mean;
 
         // Create comparisons for all other benchmarks
         let mut comparisons = Vec::new();
-        for (i, result) in results.iter().enumerate() {
+        for (i, &(result, summary)) in results.iter().enumerate() {
             if i == fastest_idx {
                 continue; // Skip the fastest one (it would just be 1.0× faster than itself)
             }
@@ -190,11 +321,11 @@ impl ResultAnalyzer {
             };
 
             // Calculate how many times slower this benchmark is
-            let times_slower = result.summary.mean / fastest_mean;
+            let times_slower = summary.mean / fastest_mean;
 
             // Simple error propagation (approximate)
-            let relative_error_squared = (fastest.summary.std_dev / fastest_mean).powi(2)
-                + (result.summary.std_dev / result.summary.mean).powi(2);
+            let relative_error_squared = (fastest_summary.std_dev / fastest_mean).powi(2)
+                + (summary.std_dev / summary.mean).powi(2);
             let error = times_slower * relative_error_squared.sqrt();
 
             comparisons.push(SpeedComparison {
@@ -218,3 +349,119 @@ impl ResultAnalyzer {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(iteration: usize, status: RunStatus, rusage: Option<ResourceUsage>) -> RunResult {
+        RunResult {
+            iteration,
+            duration_ms: 1000.0,
+            exit_code: 0,
+            status,
+            failure: None,
+            stop_reason: StopReason::default(),
+            instrumentation: InstrumentationType::Uninstrumented,
+            output: None,
+            profile: None,
+            datadir_sizes: BTreeMap::new(),
+            cgroup: None,
+            limits: None,
+            rusage,
+            metrics: BTreeMap::new(),
+            missed_milestones: Vec::new(),
+            validation_timing: BTreeMap::new(),
+            chain_state: None,
+            prune: None,
+            hooks: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_rusage_summary() {
+        let usage = |user_time_ms: f64, max_rss: u64| {
+            Some(ResourceUsage {
+                user_time_ms,
+                max_rss,
+                ..Default::default()
+            })
+        };
+        let results = vec![
+            run(0, RunStatus::Completed, usage(100.0, 1000)),
+            run(1, RunStatus::Stalled, usage(900.0, 9000)),
+            run(2, RunStatus::Completed, usage(300.0, 3000)),
+            run(3, RunStatus::Failed, usage(50.0, 500)),
+            run(4, RunStatus::Completed, None),
+        ];
+
+        let summary = ResultAnalyzer::calculate_summary(&results).unwrap();
+        assert_eq!(summary.metrics.len(), 9);
+        let user_time = &summary.metrics["user_time_ms"];
+        assert_eq!(user_time.min, 100.0);
+        assert_eq!(user_time.max, 300.0);
+        assert_eq!(user_time.mean, 200.0);
+        let max_rss = &summary.metrics["max_rss"];
+        assert_eq!(max_rss.min, 1000.0);
+        assert_eq!(max_rss.max, 3000.0);
+        assert_eq!(summary.metrics["major_faults"].max, 0.0);
+
+        assert!(ResultAnalyzer::calculate_summary(&results[1..2]).is_none());
+    }
+
+    #[test]
+    fn test_milestone_summary() {
+        let milestones = |times: &[(u64, f64)]| {
+            times
+                .iter()
+                .map(|(height, ms)| (format!("time_to_height_{height}_ms"), *ms))
+                .collect()
+        };
+        let mut results = vec![
+            run(0, RunStatus::Completed, None),
+            run(1, RunStatus::Completed, None),
+            run(2, RunStatus::TimedOut, None),
+        ];
+        results[0].metrics = milestones(&[(100, 1000.0), (200, 3000.0)]);
+        // Never reached the second milestone
+        results[1].metrics = milestones(&[(100, 2000.0)]);
+        results[2].metrics = milestones(&[(100, 500.0), (200, 900.0)]);
+
+        let summary = ResultAnalyzer::calculate_summary(&results).unwrap();
+        assert_eq!(summary.metrics.len(), 2);
+        let first = &summary.metrics["time_to_height_100_ms"];
+        assert_eq!(first.min, 1000.0);
+        assert_eq!(first.max, 2000.0);
+        assert_eq!(first.mean, 1500.0);
+        let second = &summary.metrics["time_to_height_200_ms"];
+        assert_eq!(second.min, 3000.0);
+        assert_eq!(second.max, 3000.0);
+    }
+
+    #[test]
+    fn test_master_summary_skips_incomplete() {
+        let cell = |dbcache: &str, runs: Vec<RunResult>| BenchmarkResult {
+            command: "bitcoind".to_string(),
+            parameters: HashMap::from([("dbcache".to_string(), dbcache.to_string())]),
+            summary: ResultAnalyzer::calculate_summary(&runs),
+            runs,
+            hooks: Vec::new(),
+        };
+        let mut slow = run(0, RunStatus::Completed, None);
+        slow.duration_ms = 2000.0;
+        let results = [
+            cell("450", vec![run(0, RunStatus::Completed, None)]),
+            cell("100", vec![run(0, RunStatus::TimedOut, None)]),
+            cell("1000", vec![slow]),
+        ];
+        assert!(results[1].summary.is_none());
+
+        let master = ResultAnalyzer::calculate_master_summary(&results).unwrap();
+        assert_eq!(master.fastest_parameters["dbcache"], "450");
+        assert_eq!(master.comparisons.len(), 1);
+        assert_eq!(master.comparisons[0].reference_label, "dbcache=1000");
+        assert_eq!(master.comparisons[0].times_faster, 2.0);
+
+        assert!(ResultAnalyzer::calculate_master_summary(&results[1..2]).is_none());
+    }
+}
@@ -3,9 +3,16 @@ use clap::ValueEnum;
 use log::{debug, info};
 use std::path::{Path, PathBuf};
 
+use crate::benchmarks::chain_state::ChainState;
 use crate::benchmarks::hook_runner::HookArgs;
 use crate::benchmarks::parameters::ParameterList;
-use crate::benchmarks::utils::check_binaries_exist;
+use crate::benchmarks::peer::ManagedPeer;
+use crate::benchmarks::rpc::DEFAULT_RPC_INTERVAL;
+use crate::benchmarks::stall::{
+    StallConfig, DEFAULT_STALL_CPU_THRESHOLD, DEFAULT_STALL_IO_THRESHOLD, DEFAULT_STALL_TIMEOUT,
+};
+use crate::benchmarks::timeline::TimelineAlignment;
+use crate::benchmarks::utils::{check_binaries_exist, DEFAULT_DATADIR_DIRS};
 use crate::config::{get_merged_options, GlobalConfig, SingleConfig};
 use crate::download::SnapshotInfo;
 use crate::path_utils;
@@ -59,7 +66,7 @@ impl Runner {
         // Check if all required binaries exist
         if let Err(missing_binaries) = check_binaries_exist(
             &self.global_config.app.bin_dir,
-            &self.global_config.bench.global.commits,
+            &self.global_config.bench.required_commits(),
         ) {
             let mut error_msg = String::from("Missing required binaries:\n");
             for (commit, path) in missing_binaries {
@@ -156,20 +163,37 @@ This can be downloaded with `benchkit snapshot download {}`",
         } else {
             crate::benchmarks::HookMode::default()
         };
-        let hook_runner = crate::benchmarks::hook_runner::HookRunner::with_mode(mode);
+        let hook_runner = crate::benchmarks::hook_runner::HookRunner::with_mode(mode)
+            .scripts(bench.hooks.clone().unwrap_or_default());
 
-        // Create benchmark runner with optional profiling
-        let benchmark_runner = crate::benchmarks::benchmark_runner::BenchmarkRunner::builder(
-            self.out_dir.clone(),
-            hook_runner,
-        )
-        .capture_output(options.capture_output)
-        .parameter_lists(parameter_lists)
-        .profiling(options.profile.unwrap_or(false), options.profile_interval)
-        .benchmark_cores(self.global_config.bench.global.benchmark_cores.clone())
-        .stop_on_log_pattern(options.stop_on_log_pattern.clone())
-        .perf_instrumentation(options.perf_instrumentation.unwrap_or(false))
-        .build()?;
+        // Start the peer the benchmark connects to, if benchkit manages it
+        let peer = bench
+            .peer
+            .as_ref()
+            .map(|peer| {
+                let mut datadir = self
+                    .global_config
+                    .bench
+                    .global
+                    .tmp_data_dir
+                    .clone()
+                    .into_os_string();
+                datadir.push("-peer");
+                ManagedPeer::start(
+                    peer,
+                    peer.cores
+                        .clone()
+                        .or(self.global_config.bench.global.runner_cores.clone()),
+                    &bench.network,
+                    &PathBuf::from(datadir),
+                    &self.out_dir,
+                )
+            })
+            .transpose()?;
+        let connect = match &peer {
+            Some(peer) => peer.address().to_string(),
+            None => bench.connect.clone().unwrap_or_default(),
+        };
 
         // Get snapshot info
         let snapshot_path = if let Some(snapshot_info) = SnapshotInfo::for_network(
@@ -185,38 +209,113 @@ This can be downloaded with `benchkit snapshot download {}`",
             self.global_config.app.snapshot_dir.clone() // Fallback
         };
 
-        // Get command template
-        let command_template = match &options.command {
-            Some(cmd) => crate::benchmarks::utils::build_benchmark_command(
-                &self.global_config.app.bin_dir,
-                "{commit}",
-                &bench.network,
-                &self.global_config.bench.global.tmp_data_dir,
-                &bench.connect.clone().unwrap_or_default(),
-                cmd,
-            ),
-            None => anyhow::bail!(
-                "No command template specified for benchmark: {}",
-                bench.name
-            ),
-        };
-
         // Hooks are the various hyperfine-esque prepare/setup/conclude/cleanup scripts
         let hook_args = HookArgs {
             binary: format!(
                 "{}/bitcoind-{{commit}}",
                 self.global_config.app.bin_dir.display()
             ),
-            connect_address: bench.connect.clone().unwrap_or_default(),
+            connect_address: connect.clone(),
             network: bench.network.clone(),
             out_dir: self.out_dir.clone(),
             snapshot_path,
+            source_datadir: bench.source_datadir.clone(),
+            upgrade_from: bench.upgrade_from.clone(),
+            upgrade_height: bench.upgrade_height,
+            block_source: bench.block_source.clone(),
+            load_blocks: bench.load_blocks.clone().unwrap_or_default(),
+            prune: bench.prune.clone(),
             tmp_data_dir: self.global_config.bench.global.tmp_data_dir.clone(),
             iteration: 0,
             commit: "{commit}".to_string(), // Will be replaced by parameter substitution
             params_dir: "default".to_string(), // Will be updated during parameter matrix expansion
         };
 
+        let mode_args = hook_runner.command_args(&hook_args)?;
+
+        // Stall detection is on by default when profiling, and otherwise only
+        // when a timeout is configured. A timeout of zero disables it.
+        let stall_timeout = options.stall_timeout.or(options
+            .profile
+            .unwrap_or(false)
+            .then_some(DEFAULT_STALL_TIMEOUT));
+        let stall_config =
+            stall_timeout
+                .filter(|timeout| *timeout > 0)
+                .map(|timeout| StallConfig {
+                    timeout: std::time::Duration::from_secs(timeout),
+                    cpu_threshold: options
+                        .stall_cpu_threshold
+                        .unwrap_or(DEFAULT_STALL_CPU_THRESHOLD),
+                    io_threshold: options
+                        .stall_io_threshold
+                        .unwrap_or(DEFAULT_STALL_IO_THRESHOLD),
+                });
+
+        // Create benchmark runner with optional profiling
+        let benchmark_runner = crate::benchmarks::benchmark_runner::BenchmarkRunner::builder(
+            self.out_dir.clone(),
+            hook_runner,
+        )
+        .capture_output(options.capture_output)
+        .parameter_lists(parameter_lists)
+        .profiling(options.profile.unwrap_or(false), options.profile_interval)
+        .benchmark_cores(self.global_config.bench.global.benchmark_cores.clone())
+        .stop_on_log_pattern(options.stop_on_log_pattern.clone())
+        .log_rules(options.log_rules.clone().unwrap_or_default())
+        .perf_instrumentation(options.perf_instrumentation.unwrap_or(false))
+        .datadir_dirs(
+            options
+                .datadir_dirs
+                .clone()
+                .unwrap_or_else(|| DEFAULT_DATADIR_DIRS.iter().map(|d| d.to_string()).collect()),
+        )
+        .interference_threshold(options.interference_threshold)
+        .cgroup_root(self.global_config.bench.global.cgroup_root.clone())
+        .limits(bench.limits.clone())
+        .stall_detection(stall_config)
+        .timeout(options.timeout.map(std::time::Duration::from_secs))
+        .timeline(
+            options
+                .timeline_alignment
+                .as_deref()
+                .map(TimelineAlignment::alignment_from_str)
+                .transpose()?
+                .unwrap_or_default(),
+            options.timeline_bucket,
+        )
+        .milestones(options.milestones.clone().unwrap_or_default())
+        .validation_timing(options.validation_timing.unwrap_or(false))
+        .rpc_metrics(
+            options
+                .rpc_metrics
+                .unwrap_or(false)
+                .then(|| options.rpc_interval.unwrap_or(DEFAULT_RPC_INTERVAL)),
+        )
+        .verify_chain_state(options.verify_chain_state.unwrap_or(false))
+        .stop_at_height(options.stop_at_height)
+        .measure(options.measure_from.clone(), options.measure_to.clone())
+        .peer(peer)
+        .prune(bench.prune.clone())
+        .build()?;
+
+        // Get command template
+        let command_template = match &options.command {
+            Some(cmd) => crate::benchmarks::utils::build_benchmark_command(
+                &self.global_config.app.bin_dir,
+                "{commit}",
+                &bench.network,
+                &self.global_config.bench.global.tmp_data_dir,
+                &connect,
+                &mode_args,
+                cmd,
+            ),
+            None => anyhow::bail!(
+                "No command template specified for benchmark: {}",
+                bench.name
+            ),
+        };
+
         let results =
             benchmark_runner.run_parameter_matrix(&command_template, options.runs, &hook_args)?;
 
@@ -226,6 +325,12 @@ This can be downloaded with `benchkit snapshot download {}`",
             &export_path,
         )?;
 
+        // Checked after exporting, so diverging runs can be inspected
+        if options.verify_chain_state.unwrap_or(false) {
+            ChainState::verify_consistent(&results)
+                .with_context(|| format!("Chain state verification failed for {}", bench.name))?;
+        }
+
         info!("Benchmark {} completed successfully", bench.name);
         Ok(())
     }
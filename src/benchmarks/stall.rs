@@ -0,0 +1,126 @@
+use std::time::{Duration, Instant};
+
+use crate::benchmarks::procfs;
+
+/// Default period without activity after which a run is considered stalled
+pub const DEFAULT_STALL_TIMEOUT: u64 = 300;
+
+/// Default CPU usage (percent of one core) that counts as activity
+pub const DEFAULT_STALL_CPU_THRESHOLD: f64 = 0.5;
+
+/// Default I/O rate (bytes/s) above which the process counts as active
+pub const DEFAULT_STALL_IO_THRESHOLD: u64 = 0;
+
+/// Settings for stall detection
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StallConfig {
+    /// Period without activity after which the run is considered stalled
+    pub timeout: Duration,
+    /// CPU usage (percent of one core) above which the process tree is active
+    pub cpu_threshold: f64,
+    /// I/O rate (bytes/s) above which the process tree is active
+    pub io_threshold: u64,
+}
+
+impl Default for StallConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(DEFAULT_STALL_TIMEOUT),
+            cpu_threshold: DEFAULT_STALL_CPU_THRESHOLD,
+            io_threshold: DEFAULT_STALL_IO_THRESHOLD,
+        }
+    }
+}
+
+impl StallConfig {
+    /// Interval between activity checks, short enough to detect a stall
+    /// promptly without sampling more than necessary
+    pub fn check_interval(&self) -> Duration {
+        (self.timeout / 30).clamp(Duration::from_secs(1), Duration::from_secs(10))
+    }
+}
+
+/// Tracks activity of a process tree and reports when it has stalled
+///
+/// Activity is measured from the cumulative CPU ticks and storage I/O of the
+/// tree, so it works without the profiler.
+#[derive(Debug)]
+pub struct StallDetector {
+    config: StallConfig,
+    ticks_per_second: f64,
+    last_check: Option<(Instant, u64, u64)>,
+    last_active: Instant,
+}
+
+impl StallDetector {
+    /// Create a detector, treating the process as active from now
+    pub fn new(config: StallConfig) -> Self {
+        let ticks_per_second = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+        Self {
+            config,
+            ticks_per_second: if ticks_per_second > 0 {
+                ticks_per_second as f64
+            } else {
+                100.0
+            },
+            last_check: None,
+            last_active: Instant::now(),
+        }
+    }
+
+    /// Read the activity counters of `pids` and return whether the tree has
+    /// been inactive for longer than the timeout
+    pub fn check(&mut self, pids: &[u32]) -> bool {
+        self.observe(
+            Instant::now(),
+            procfs::cpu_ticks(pids),
+            procfs::io_bytes(pids),
+        )
+    }
+
+    /// Record cumulative CPU ticks and I/O bytes observed at `now`
+    pub fn observe(&mut self, now: Instant, cpu_ticks: u64, io_bytes: u64) -> bool {
+        if let Some((then, prev_ticks, prev_io)) = self.last_check {
+            let elapsed = now.duration_since(then).as_secs_f64();
+            if elapsed > 0.0 {
+                let cpu =
+                    cpu_ticks.saturating_sub(prev_ticks) as f64 / self.ticks_per_second / elapsed
+                        * 100.0;
+                let io_rate = io_bytes.saturating_sub(prev_io) as f64 / elapsed;
+                if cpu > self.config.cpu_threshold || io_rate > self.config.io_threshold as f64 {
+                    self.last_active = now;
+                }
+            }
+        }
+        self.last_check = Some((now, cpu_ticks, io_bytes));
+
+        now.duration_since(self.last_active) > self.config.timeout
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stall_detector() {
+        let config = StallConfig {
+            timeout: Duration::from_secs(60),
+            cpu_threshold: 1.0,
+            io_threshold: 1024,
+        };
+        let mut detector = StallDetector::new(config);
+        detector.ticks_per_second = 100.0;
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+
+        assert!(!detector.observe(at(0), 0, 0));
+        // Busy CPU keeps the process active
+        assert!(!detector.observe(at(30), 1500, 0));
+        // Idle CPU and a trickle of I/O below the threshold
+        assert!(!detector.observe(at(60), 1510, 10_000));
+        assert!(detector.observe(at(91), 1520, 20_000));
+        // I/O above the threshold counts as activity again
+        assert!(!detector.observe(at(100), 1520, 10_000_000));
+    }
+}
@@ -0,0 +1,803 @@
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::os::unix::process::ExitStatusExt;
+use std::path::PathBuf;
+use std::process::{Child, ExitStatus, Output};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::benchmarks::log_monitor::{LogAction, LogMonitor, LogReport, LogRule};
+use crate::benchmarks::procfs;
+use crate::benchmarks::profiler::{ProfileResult, Profiler};
+use crate::benchmarks::rpc::{HeightWatcher, RpcClient, RpcCollection, RpcCollector};
+use crate::benchmarks::stall::{StallConfig, StallDetector};
+
+/// Default time a process group is given to exit after SIGTERM
+pub const DEFAULT_TERMINATE_GRACE: Duration = Duration::from_secs(30);
+
+/// Why a supervised process stopped
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    /// The process exited on its own
+    #[default]
+    Exited,
+    /// The stop log pattern was matched
+    LogPattern,
+    /// The node reached the stop height
+    Height,
+    /// A log rule reported a failure
+    LogFailure,
+    /// The process showed no activity for the stall timeout
+    Stalled,
+    /// The run exceeded its timeout
+    TimedOut,
+}
+
+/// Name of the log mark that starts the measured interval
+const MEASURE_FROM: &str = "measure_from";
+/// Name of the log mark that ends the measured interval
+const MEASURE_TO: &str = "measure_to";
+
+/// Where the measured interval of a run starts or ends
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum MeasureMarker {
+    /// The first log line matching a regex
+    Log(String),
+    /// The node reaching a height, polled over RPC
+    Height(u64),
+}
+
+impl MeasureMarker {
+    fn log_rule(&self, name: &str) -> Option<LogRule> {
+        match self {
+            MeasureMarker::Log(pattern) => Some(LogRule {
+                pattern: pattern.clone(),
+                action: LogAction::Mark,
+                name: Some(name.to_string()),
+            }),
+            MeasureMarker::Height(_) => None,
+        }
+    }
+
+    fn height(&self) -> Option<u64> {
+        match self {
+            MeasureMarker::Log(_) => None,
+            MeasureMarker::Height(height) => Some(*height),
+        }
+    }
+}
+
+/// Resource usage of a process and the descendants it waited for, as
+/// reported by `wait4`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    /// CPU time spent in user mode, in milliseconds
+    pub user_time_ms: f64,
+    /// CPU time spent in kernel mode, in milliseconds
+    pub system_time_ms: f64,
+    /// Peak resident set size of the largest process, in bytes
+    pub max_rss: u64,
+    /// Minor page faults
+    pub minor_faults: u64,
+    /// Major page faults
+    pub major_faults: u64,
+    /// Block input operations
+    pub block_input_ops: u64,
+    /// Block output operations
+    pub block_output_ops: u64,
+    /// Voluntary context switches
+    pub voluntary_ctxt_switches: u64,
+    /// Involuntary context switches
+    pub involuntary_ctxt_switches: u64,
+}
+
+impl ResourceUsage {
+    /// Convert from the raw `rusage` struct
+    pub fn from_rusage(usage: &libc::rusage) -> Self {
+        let millis = |tv: libc::timeval| tv.tv_sec as f64 * 1000.0 + tv.tv_usec as f64 / 1000.0;
+        Self {
+            user_time_ms: millis(usage.ru_utime),
+            system_time_ms: millis(usage.ru_stime),
+            // ru_maxrss is in kilobytes on Linux
+            max_rss: usage.ru_maxrss as u64 * 1024,
+            minor_faults: usage.ru_minflt as u64,
+            major_faults: usage.ru_majflt as u64,
+            block_input_ops: usage.ru_inblock as u64,
+            block_output_ops: usage.ru_oublock as u64,
+            voluntary_ctxt_switches: usage.ru_nvcsw as u64,
+            involuntary_ctxt_switches: usage.ru_nivcsw as u64,
+        }
+    }
+
+    /// Named values for summarising across runs
+    pub fn metrics(&self) -> [(&'static str, f64); 9] {
+        [
+            ("user_time_ms", self.user_time_ms),
+            ("system_time_ms", self.system_time_ms),
+            ("max_rss", self.max_rss as f64),
+            ("minor_faults", self.minor_faults as f64),
+            ("major_faults", self.major_faults as f64),
+            ("block_input_ops", self.block_input_ops as f64),
+            ("block_output_ops", self.block_output_ops as f64),
+            (
+                "voluntary_ctxt_switches",
+                self.voluntary_ctxt_switches as f64,
+            ),
+            (
+                "involuntary_ctxt_switches",
+                self.involuntary_ctxt_switches as f64,
+            ),
+        ]
+    }
+}
+
+/// Everything observed while supervising a process
+#[derive(Debug)]
+pub struct SupervisedRun {
+    /// Exit status and captured output of the process
+    pub output: Output,
+    /// Profile of the process, if profiling
+    pub profile: Option<ProfileResult>,
+    /// Why the process stopped
+    pub stop_reason: StopReason,
+    /// Resource usage reported when the process was reaped
+    pub rusage: ResourceUsage,
+    /// What the log rules observed
+    pub log: LogReport,
+    /// Time from the start of supervision until the process exited or a stop
+    /// condition was met
+    pub duration: Duration,
+    /// Time between the measurement markers, if any are set and all of them
+    /// were seen
+    pub window: Option<Duration>,
+}
+
+/// Builder for ProcessSupervisor
+pub struct ProcessSupervisorBuilder {
+    profiler: Option<Profiler>,
+    rpc_collector: Option<RpcCollector>,
+    rpc_client: Option<RpcClient>,
+    stop_height: Option<u64>,
+    measure_from: Option<MeasureMarker>,
+    measure_to: Option<MeasureMarker>,
+    log_rules: Vec<LogRule>,
+    milestones: Vec<u64>,
+    log_file: Option<PathBuf>,
+    capture_output: bool,
+    stall_config: Option<StallConfig>,
+    timeout: Option<Duration>,
+    poll_interval: Duration,
+    terminate_grace: Duration,
+}
+
+impl Default for ProcessSupervisorBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProcessSupervisorBuilder {
+    /// Create a new ProcessSupervisorBuilder with nothing enabled
+    pub fn new() -> Self {
+        Self {
+            profiler: None,
+            rpc_collector: None,
+            rpc_client: None,
+            stop_height: None,
+            measure_from: None,
+            measure_to: None,
+            log_rules: Vec::new(),
+            milestones: Vec::new(),
+            log_file: None,
+            capture_output: false,
+            stall_config: None,
+            timeout: None,
+            poll_interval: Duration::from_millis(100),
+            terminate_grace: DEFAULT_TERMINATE_GRACE,
+        }
+    }
+
+    /// Profile the process with the given profiler
+    pub fn profiler(mut self, profiler: Option<Profiler>) -> Self {
+        self.profiler = profiler;
+        self
+    }
+
+    /// Poll the node over RPC while it runs
+    pub fn rpc_collector(mut self, collector: Option<RpcCollector>) -> Self {
+        self.rpc_collector = collector;
+        self
+    }
+
+    /// Client of the node, used to stop it and to wait for heights
+    pub fn rpc_client(mut self, client: Option<RpcClient>) -> Self {
+        self.rpc_client = client;
+        self
+    }
+
+    /// Stop the node over RPC once it reaches a height
+    pub fn stop_at_height(mut self, height: Option<u64>) -> Self {
+        self.stop_height = height;
+        self
+    }
+
+    /// Measure only the interval between these markers, each defaulting to
+    /// the start or end of the run
+    pub fn measure(mut self, from: Option<MeasureMarker>, to: Option<MeasureMarker>) -> Self {
+        self.measure_from = from;
+        self.measure_to = to;
+        self
+    }
+
+    /// Stop the process when its output matches a regex pattern
+    pub fn stop_on_log_pattern(mut self, pattern: Option<String>) -> Self {
+        self.log_rules.extend(pattern.map(LogRule::stop));
+        self
+    }
+
+    /// Apply log rules to the output of the process
+    pub fn log_rules(mut self, rules: Vec<LogRule>) -> Self {
+        self.log_rules.extend(rules);
+        self
+    }
+
+    /// Record when the tip reaches each of these heights, from the
+    /// `UpdateTip` lines the log rules are applied to
+    pub fn milestones(mut self, milestones: Vec<u64>) -> Self {
+        self.milestones = milestones;
+        self
+    }
+
+    /// Apply the log rules to lines appended to this file rather than to the
+    /// output of the process
+    pub fn log_file(mut self, path: Option<PathBuf>) -> Self {
+        self.log_file = path;
+        self
+    }
+
+    /// Keep the output of the process
+    pub fn capture_output(mut self, capture: bool) -> Self {
+        self.capture_output = capture;
+        self
+    }
+
+    /// Stop the process if it stalls
+    pub fn stall_detection(mut self, config: Option<StallConfig>) -> Self {
+        self.stall_config = config;
+        self
+    }
+
+    /// Stop the process if it runs for longer than `timeout`
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set how often the stop conditions are checked
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Set how long the process group is given to exit after SIGTERM
+    pub fn terminate_grace(mut self, grace: Duration) -> Self {
+        self.terminate_grace = grace;
+        self
+    }
+
+    /// Build the ProcessSupervisor
+    ///
+    /// Lines already in the log file are skipped, so the supervisor should be
+    /// built before the process is launched.
+    pub fn build(mut self) -> ProcessSupervisor {
+        // Log markers are recorded as marks by the log rules
+        let markers = [
+            (&self.measure_from, MEASURE_FROM),
+            (&self.measure_to, MEASURE_TO),
+        ];
+        let marker_rules: Vec<LogRule> = markers
+            .iter()
+            .filter_map(|(marker, name)| marker.as_ref()?.log_rule(name))
+            .collect();
+        self.log_rules.extend(marker_rules);
+
+        let log_file = self.log_file.map(|path| {
+            let offset = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            (path, offset)
+        });
+        ProcessSupervisor {
+            profiler: self.profiler,
+            rpc_collector: self.rpc_collector,
+            rpc_client: self.rpc_client,
+            stop_height: self.stop_height,
+            measure_from: self.measure_from,
+            measure_to: self.measure_to,
+            log_rules: self.log_rules,
+            milestones: self.milestones,
+            log_file,
+            capture_output: self.capture_output,
+            stall_config: self.stall_config,
+            timeout: self.timeout,
+            poll_interval: self.poll_interval,
+            terminate_grace: self.terminate_grace,
+        }
+    }
+}
+
+/// Owns a running benchmark process and drives every form of monitoring for
+/// it from a single loop: profiling samples, log rules, stall detection and
+/// the run timeout
+pub struct ProcessSupervisor {
+    profiler: Option<Profiler>,
+    rpc_collector: Option<RpcCollector>,
+    rpc_client: Option<RpcClient>,
+    stop_height: Option<u64>,
+    measure_from: Option<MeasureMarker>,
+    measure_to: Option<MeasureMarker>,
+    log_rules: Vec<LogRule>,
+    milestones: Vec<u64>,
+    /// Followed log file and where it ended when the supervisor was built
+    log_file: Option<(PathBuf, u64)>,
+    capture_output: bool,
+    stall_config: Option<StallConfig>,
+    timeout: Option<Duration>,
+    poll_interval: Duration,
+    terminate_grace: Duration,
+}
+
+impl ProcessSupervisor {
+    /// Create a builder for ProcessSupervisor
+    pub fn builder() -> ProcessSupervisorBuilder {
+        ProcessSupervisorBuilder::new()
+    }
+
+    /// Supervise a launched process until it exits or a stop condition is
+    /// met. The process must lead its own process group, so that the whole
+    /// tree can be terminated.
+    pub fn supervise(&self, command: &str, mut child: Child) -> Result<SupervisedRun> {
+        let pid = child.id();
+        let start = Instant::now();
+        debug!("Supervising process {pid}: {command}");
+
+        // Log rules are applied to the log file if there is one, otherwise to
+        // the output, which is read by a separate monitor when capturing
+        let watch_log = !self.log_rules.is_empty() || !self.milestones.is_empty();
+        let (mut log_monitor, mut output_monitor) = match &self.log_file {
+            Some((path, offset)) => (
+                watch_log
+                    .then(|| {
+                        LogMonitor::follow(
+                            path,
+                            self.log_rules.clone(),
+                            self.milestones.clone(),
+                            *offset,
+                        )
+                    })
+                    .transpose()?,
+                self.capture_output
+                    .then(|| LogMonitor::start(&mut child, Vec::new(), Vec::new(), true))
+                    .transpose()?,
+            ),
+            None => (
+                (watch_log || self.capture_output)
+                    .then(|| {
+                        LogMonitor::start(
+                            &mut child,
+                            self.log_rules.clone(),
+                            self.milestones.clone(),
+                            self.capture_output,
+                        )
+                    })
+                    .transpose()?,
+                None,
+            ),
+        };
+        let mut profile_session = self
+            .profiler
+            .as_ref()
+            .map(|profiler| profiler.start(pid))
+            .transpose()?;
+        let rpc_collection = self.rpc_collector.as_ref().map(RpcCollector::start);
+        let mut final_snapshot = None;
+        let watch_height = |height: Option<u64>| -> Result<Option<HeightWatcher>> {
+            height
+                .map(|height| {
+                    let client = self
+                        .rpc_client
+                        .clone()
+                        .context("Waiting for a height requires an RPC client")?;
+                    Ok(HeightWatcher::start(client, height))
+                })
+                .transpose()
+        };
+        let height_watcher = watch_height(self.stop_height)?;
+        let from_watcher =
+            watch_height(self.measure_from.as_ref().and_then(MeasureMarker::height))?;
+        let to_watcher = watch_height(self.measure_to.as_ref().and_then(MeasureMarker::height))?;
+        let mut stall_detector = self.stall_config.map(StallDetector::new);
+
+        let sample_interval = self.profiler.as_ref().map(Profiler::sample_interval);
+        let stall_interval = self.stall_config.map(|c| c.check_interval());
+        let mut next_sample = start;
+        let mut next_stall_check = start + stall_interval.unwrap_or_default();
+
+        let (stop_reason, end, (status, rusage)) = loop {
+            if let Some(reaped) = reap(&child, false)? {
+                break (StopReason::Exited, Instant::now(), reaped);
+            }
+
+            let mut now = Instant::now();
+            let stop_reason = if log_monitor.as_ref().is_some_and(|m| m.failure().is_some()) {
+                warn!("Log rule reported a failure, stopping process");
+                Some(StopReason::LogFailure)
+            } else if let Some(reached) = height_watcher.as_ref().and_then(HeightWatcher::reached) {
+                // The measured interval ends when the height was reached,
+                // not when the node has shut down
+                now = reached;
+                Some(StopReason::Height)
+            } else if log_monitor.as_ref().is_some_and(LogMonitor::is_matched) {
+                info!("Log pattern matched, stopping process");
+                Some(StopReason::LogPattern)
+            } else if self.timeout.is_some_and(|t| now.duration_since(start) > t) {
+                warn!(
+                    "Run exceeded its timeout of {}s, stopping process",
+                    self.timeout.unwrap_or_default().as_secs()
+                );
+                Some(StopReason::TimedOut)
+            } else if let (Some(detector), Some(interval)) =
+                (stall_detector.as_mut(), stall_interval)
+            {
+                if now >= next_stall_check {
+                    next_stall_check = now + interval;
+                    detector.check(&procfs::process_tree(pid)).then(|| {
+                        warn!(
+                            "Process seems to be stalled (no activity for {}s), stopping process",
+                            self.stall_config.unwrap_or_default().timeout.as_secs()
+                        );
+                        StopReason::Stalled
+                    })
+                } else {
+                    None
+                }
+            } else {
+                None
+            };
+
+            if let Some(stop_reason) = stop_reason {
+                // Record the state of the node while it can still answer. A
+                // stalled, timed out or failing node may not, and would hold
+                // up its termination, so the last sample stands in for it.
+                if matches!(stop_reason, StopReason::LogPattern | StopReason::Height) {
+                    final_snapshot = rpc_collection.as_ref().map(RpcCollection::snapshot);
+                }
+                let reaped = match (&self.rpc_client, stop_reason) {
+                    (Some(client), StopReason::Height) => self.shutdown(&child, client)?,
+                    _ => self.terminate(&child)?,
+                };
+                break (stop_reason, now, reaped);
+            }
+
+            if let (Some(session), Some(interval)) = (profile_session.as_mut(), sample_interval) {
+                if now >= next_sample {
+                    next_sample = now + interval;
+                    session.sample();
+                }
+            }
+
+            thread::sleep(self.poll_interval);
+        };
+
+        let exit_code = status.code().unwrap_or(-1);
+        let profile = profile_session
+            .map(|session| session.finish(command, exit_code))
+            .transpose()?;
+        if let Some(collection) = rpc_collection {
+            collection.finish(final_snapshot)?;
+        }
+        let (stdout, stderr) = output_monitor
+            .as_mut()
+            .or(log_monitor.as_mut())
+            .map(LogMonitor::take_output)
+            .unwrap_or_default();
+        if let Some(monitor) = log_monitor.as_mut() {
+            // Wait for the rest of the log to be read
+            monitor.take_output();
+        }
+        let log = log_monitor
+            .as_ref()
+            .map(LogMonitor::report)
+            .unwrap_or_default();
+
+        if !status.success() && stop_reason == StopReason::Exited {
+            debug!("Command failed with status: {exit_code}");
+        }
+
+        let duration = end.duration_since(start);
+        let marker_time = |marker: &Option<MeasureMarker>,
+                           name: &str,
+                           watcher: &Option<HeightWatcher>,
+                           default: Duration| match marker {
+            Some(MeasureMarker::Log(_)) => log.marks.get(name).copied(),
+            Some(MeasureMarker::Height(_)) => watcher
+                .as_ref()
+                .and_then(HeightWatcher::reached)
+                .map(|reached| reached.saturating_duration_since(start)),
+            None => Some(default),
+        };
+        let window = if self.measure_from.is_some() || self.measure_to.is_some() {
+            let from = marker_time(
+                &self.measure_from,
+                MEASURE_FROM,
+                &from_watcher,
+                Duration::ZERO,
+            );
+            let to = marker_time(&self.measure_to, MEASURE_TO, &to_watcher, duration);
+            match (from, to) {
+                (Some(from), Some(to)) => Some(to.min(duration).saturating_sub(from)),
+                _ => {
+                    warn!("Measurement markers were not all seen");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        Ok(SupervisedRun {
+            output: Output {
+                status,
+                stdout,
+                stderr,
+            },
+            profile,
+            stop_reason,
+            rusage,
+            log,
+            duration,
+            window,
+        })
+    }
+
+    /// Ask the node to stop over RPC, falling back to SIGTERM, and escalate to
+    /// SIGKILL if it has not exited after the grace period
+    fn shutdown(&self, child: &Child, client: &RpcClient) -> Result<(ExitStatus, ResourceUsage)> {
+        match client.call("stop", serde_json::json!([])) {
+            Ok(_) => self.wait_or_kill(child),
+            Err(e) => {
+                warn!("Failed to stop node over RPC, sending SIGTERM: {e:#}");
+                self.terminate(child)
+            }
+        }
+    }
+
+    /// Send SIGTERM to the process group, escalating to SIGKILL if it has not
+    /// exited after the grace period
+    fn terminate(&self, child: &Child) -> Result<(ExitStatus, ResourceUsage)> {
+        unsafe {
+            libc::kill(-(child.id() as i32), libc::SIGTERM);
+        }
+        self.wait_or_kill(child)
+    }
+
+    /// Wait for the process to exit, sending SIGKILL to the process group if
+    /// it has not after the grace period
+    fn wait_or_kill(&self, child: &Child) -> Result<(ExitStatus, ResourceUsage)> {
+        let pgid = -(child.id() as i32);
+        let deadline = Instant::now() + self.terminate_grace;
+        while Instant::now() < deadline {
+            if let Some(reaped) = reap(child, false)? {
+                return Ok(reaped);
+            }
+            thread::sleep(self.poll_interval);
+        }
+
+        warn!(
+            "Process did not exit within {}s of being stopped, killing",
+            self.terminate_grace.as_secs()
+        );
+        unsafe {
+            libc::kill(pgid, libc::SIGKILL);
+        }
+        reap(child, true)?.context("Failed to wait for terminated process")
+    }
+}
+
+/// Reap a child with `wait4`, collecting its resource usage along with its
+/// exit status. Returns `None` if `block` is false and the child is still
+/// running.
+///
+/// The child must not be waited on through `Child` afterwards, as it has
+/// already been reaped.
+fn reap(child: &Child, block: bool) -> Result<Option<(ExitStatus, ResourceUsage)>> {
+    let mut status = 0;
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    let flags = if block { 0 } else { libc::WNOHANG };
+
+    loop {
+        let pid = unsafe { libc::wait4(child.id() as i32, &mut status, flags, &mut usage) };
+        match pid {
+            0 => return Ok(None),
+            -1 => {
+                let err = std::io::Error::last_os_error();
+                if err.kind() == std::io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(err).context("Failed to wait for process");
+            }
+            _ => {
+                return Ok(Some((
+                    ExitStatus::from_raw(status),
+                    ResourceUsage::from_rusage(&usage),
+                )))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::{Command, Stdio};
+
+    fn spawn(script: &str) -> Child {
+        use std::os::unix::process::CommandExt;
+        Command::new("sh")
+            .args(["-c", script])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .process_group(0)
+            .spawn()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_supervise_exit_with_output() {
+        let supervisor = ProcessSupervisor::builder().capture_output(true).build();
+        let run = supervisor
+            .supervise("echo", spawn("echo hello; echo oops >&2"))
+            .unwrap();
+
+        assert_eq!(run.stop_reason, StopReason::Exited);
+        assert!(run.output.status.success());
+        assert!(run.rusage.max_rss > 0);
+        assert_eq!(String::from_utf8_lossy(&run.output.stdout), "hello\n");
+        assert_eq!(String::from_utf8_lossy(&run.output.stderr), "oops\n");
+    }
+
+    #[test]
+    fn test_supervise_stop_conditions() {
+        let supervisor = ProcessSupervisor::builder()
+            .stop_on_log_pattern(Some("height=2$".to_string()))
+            .timeout(Some(Duration::from_secs(30)))
+            .build();
+        let run = supervisor
+            .supervise(
+                "loop",
+                spawn("i=0; while true; do echo height=$i; i=$((i+1)); sleep 0.1; done"),
+            )
+            .unwrap();
+        assert_eq!(run.stop_reason, StopReason::LogPattern);
+        assert!(run.duration < Duration::from_secs(10));
+
+        let supervisor = ProcessSupervisor::builder()
+            .timeout(Some(Duration::from_millis(300)))
+            .build();
+        let run = supervisor.supervise("sleep", spawn("sleep 30")).unwrap();
+        assert_eq!(run.stop_reason, StopReason::TimedOut);
+        assert!(!run.output.status.success());
+    }
+
+    #[test]
+    fn test_supervise_log_rules() {
+        let rule = |pattern: &str, action, name: Option<&str>| LogRule {
+            pattern: pattern.to_string(),
+            action,
+            name: name.map(str::to_string),
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("debug.log");
+        let supervisor = ProcessSupervisor::builder()
+            .log_rules(vec![
+                rule("^loaded", LogAction::Mark, Some("loaded")),
+                rule("^sync start", LogAction::StartTimer, Some("sync")),
+                rule("^sync start", LogAction::Mark, Some("sync_start")),
+                rule(
+                    r"^sync done cache=(?P<cache_mib>[0-9.]+)",
+                    LogAction::StopTimer,
+                    Some("sync"),
+                ),
+                rule("^sync done", LogAction::Mark, Some("sync_done")),
+                rule("Corrupted block database", LogAction::Fail, None),
+            ])
+            .log_file(Some(log.clone()))
+            .build();
+
+        // The lines are written while the process runs, with a gap between
+        // the start and end of the timed section
+        let writer = thread::spawn(move || {
+            let append = |line: &str| {
+                use std::io::Write;
+                let mut file = std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&log)
+                    .unwrap();
+                writeln!(file, "{line}").unwrap();
+            };
+            append("loaded");
+            append("sync start");
+            thread::sleep(Duration::from_millis(200));
+            append("sync done cache=12.5");
+            append("Corrupted block database");
+        });
+        let run = supervisor.supervise("log", spawn("sleep 30")).unwrap();
+        writer.join().unwrap();
+
+        assert_eq!(run.stop_reason, StopReason::LogFailure);
+        assert_eq!(run.log.failure.as_deref(), Some("Corrupted block database"));
+        assert!(run.log.marks.contains_key("loaded"));
+        // The timer runs from when the start line is read to when the end
+        // line is read, whenever the follower gets to them
+        assert_eq!(
+            run.log.timers["sync"],
+            run.log.marks["sync_done"] - run.log.marks["sync_start"]
+        );
+        assert_eq!(run.log.captures["cache_mib"], 12.5);
+    }
+
+    #[test]
+    fn test_supervise_fail_then_exit() {
+        let supervisor = ProcessSupervisor::builder()
+            .log_rules(vec![LogRule {
+                pattern: "Corrupted block database".to_string(),
+                action: LogAction::Fail,
+                name: None,
+            }])
+            .build();
+        // The node shuts down by itself before the failure is seen
+        let run = supervisor
+            .supervise(
+                "corrupt",
+                spawn("echo Corrupted block database detected; exit 1"),
+            )
+            .unwrap();
+
+        assert_eq!(run.stop_reason, StopReason::Exited);
+        assert!(!run.output.status.success());
+        assert_eq!(
+            run.log.failure.as_deref(),
+            Some("Corrupted block database detected")
+        );
+    }
+
+    #[test]
+    fn test_supervise_measure_window() {
+        let supervisor = ProcessSupervisor::builder()
+            .measure(
+                Some(MeasureMarker::Log("^loaded".to_string())),
+                Some(MeasureMarker::Log("^synced".to_string())),
+            )
+            .build();
+        let run = supervisor
+            .supervise(
+                "window",
+                spawn("sleep 0.3; echo loaded; sleep 0.5; echo synced; sleep 0.3"),
+            )
+            .unwrap();
+        let window = run.window.unwrap();
+        assert!(window >= Duration::from_millis(300));
+        assert!(window < run.duration - Duration::from_millis(300));
+
+        // A marker that is never seen leaves no window
+        let supervisor = ProcessSupervisor::builder()
+            .measure(Some(MeasureMarker::Log("^never".to_string())), None)
+            .build();
+        let run = supervisor
+            .supervise("window", spawn("echo loaded"))
+            .unwrap();
+        assert_eq!(run.window, None);
+    }
+}
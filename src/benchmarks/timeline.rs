@@ -0,0 +1,303 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use crate::benchmarks::debug_log::TipUpdate;
+use crate::benchmarks::profiler::{ProfileResult, ProfileSample};
+
+/// Default bucket width, in blocks, when aligning by height
+pub const DEFAULT_HEIGHT_BUCKET: u64 = 1000;
+
+/// How samples from different runs are lined up on a timeline
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimelineAlignment {
+    /// Seconds since profiling started
+    #[default]
+    Time,
+    /// Chain height reached when the sample was taken, from debug.log
+    Height,
+}
+
+impl TimelineAlignment {
+    /// Parse timeline alignment from string
+    pub fn alignment_from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "time" => Ok(TimelineAlignment::Time),
+            "height" => Ok(TimelineAlignment::Height),
+            _ => anyhow::bail!("Unknown timeline alignment: {}", s),
+        }
+    }
+}
+
+/// Mean, minimum and maximum of a metric across runs
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Band {
+    pub mean: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Band {
+    fn from_values(values: &[f64]) -> Self {
+        Self {
+            mean: values.iter().sum::<f64>() / values.len() as f64,
+            min: values.iter().copied().fold(f64::INFINITY, f64::min),
+            max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        }
+    }
+}
+
+/// A bucket of the timeline with the spread of each metric across runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelinePoint {
+    /// Start of the bucket, in seconds or blocks depending on the alignment
+    pub position: f64,
+    /// Number of runs with samples in this bucket
+    pub runs: usize,
+    /// Band of each metric, from the mean of each run within the bucket
+    pub metrics: BTreeMap<String, Band>,
+}
+
+/// Profile samples from all runs of a (commit, params) cell combined onto a
+/// common axis
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Timeline {
+    /// What the position of each point refers to
+    pub alignment: TimelineAlignment,
+    /// Width of each bucket, in seconds or blocks
+    pub bucket: f64,
+    /// Number of runs combined
+    pub runs: usize,
+    /// Buckets in increasing position
+    pub points: Vec<TimelinePoint>,
+}
+
+/// Samples of a single run, each with its position on the timeline
+pub type AlignedSamples<'a> = Vec<(f64, &'a ProfileSample)>;
+
+impl Timeline {
+    /// Position the samples of a run by seconds since profiling started
+    pub fn align_by_time(profile: &ProfileResult) -> AlignedSamples<'_> {
+        profile
+            .samples
+            .iter()
+            .map(|sample| (sample.time as f64, sample))
+            .collect()
+    }
+
+    /// Position the samples of a run by the chain height at the time they
+    /// were taken. Samples taken before the first tip update are dropped, as
+    /// are all samples of a profile without a start time.
+    pub fn align_by_height<'a>(
+        profile: &'a ProfileResult,
+        tips: &[TipUpdate],
+    ) -> AlignedSamples<'a> {
+        let Some(start_time) = profile.start_time else {
+            return Vec::new();
+        };
+        profile
+            .samples
+            .iter()
+            .filter_map(|sample| {
+                let time = start_time + sample.time as f64;
+                let logged = tips.partition_point(|tip| tip.time <= time);
+                logged
+                    .checked_sub(1)
+                    .map(|i| (tips[i].height as f64, sample))
+            })
+            .collect()
+    }
+
+    /// Combine aligned runs into buckets of the given width
+    pub fn aggregate(alignment: TimelineAlignment, bucket: f64, runs: &[AlignedSamples]) -> Self {
+        // Values of each metric per bucket, one mean per run
+        let mut buckets: BTreeMap<i64, (usize, BTreeMap<String, Vec<f64>>)> = BTreeMap::new();
+
+        for run in runs {
+            let mut run_buckets: BTreeMap<i64, BTreeMap<String, (f64, usize)>> = BTreeMap::new();
+            for (position, sample) in run {
+                let index = (position / bucket).floor() as i64;
+                let metrics = run_buckets.entry(index).or_default();
+                for (name, value) in sample.metrics() {
+                    let (sum, count) = metrics.entry(name).or_default();
+                    *sum += value;
+                    *count += 1;
+                }
+            }
+
+            for (index, metrics) in run_buckets {
+                let (run_count, values) = buckets.entry(index).or_default();
+                *run_count += 1;
+                for (name, (sum, count)) in metrics {
+                    values.entry(name).or_default().push(sum / count as f64);
+                }
+            }
+        }
+
+        let points = buckets
+            .into_iter()
+            .map(|(index, (runs, values))| TimelinePoint {
+                position: index as f64 * bucket,
+                runs,
+                metrics: values
+                    .into_iter()
+                    .map(|(name, values)| (name, Band::from_values(&values)))
+                    .collect(),
+            })
+            .collect();
+
+        Self {
+            alignment,
+            bucket,
+            runs: runs.len(),
+            points,
+        }
+    }
+
+    /// Write the timeline to `timeline.json` and `timeline.csv` in `dir`
+    pub fn export(&self, dir: &Path) -> Result<()> {
+        let json_path = dir.join("timeline.json");
+        let json_data =
+            serde_json::to_string_pretty(self).context("Failed to serialize timeline")?;
+        std::fs::write(&json_path, json_data)
+            .with_context(|| format!("Failed to write {}", json_path.display()))?;
+
+        let csv_path = dir.join("timeline.csv");
+        self.export_csv(&csv_path)
+            .with_context(|| format!("Failed to write {}", csv_path.display()))
+    }
+
+    fn export_csv(&self, path: &Path) -> Result<()> {
+        let names: BTreeSet<&String> = self
+            .points
+            .iter()
+            .flat_map(|point| point.metrics.keys())
+            .collect();
+
+        let mut file = File::create(path)?;
+        write!(file, "position,runs")?;
+        for name in &names {
+            write!(file, ",{name}_mean,{name}_min,{name}_max")?;
+        }
+        writeln!(file)?;
+
+        for point in &self.points {
+            write!(file, "{},{}", point.position, point.runs)?;
+            for name in &names {
+                match point.metrics.get(*name) {
+                    Some(band) => write!(file, ",{},{},{}", band.mean, band.min, band.max)?,
+                    None => write!(file, ",,,")?,
+                }
+            }
+            writeln!(file)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(start_time: f64, samples: &[(u64, u64)]) -> ProfileResult {
+        let samples = samples
+            .iter()
+            .map(|&(time, memory)| ProfileSample {
+                time,
+                memory,
+                ..Default::default()
+            })
+            .collect();
+        ProfileResult {
+            command: "bitcoind".to_string(),
+            duration: 0.0,
+            exit_code: 0,
+            start_time: Some(start_time),
+            samples,
+            interference: None,
+        }
+    }
+
+    #[test]
+    fn test_aggregate_by_time() {
+        let a = profile(0.0, &[(0, 100), (5, 200), (10, 300)]);
+        let b = profile(0.0, &[(0, 300), (6, 400)]);
+        let runs = [Timeline::align_by_time(&a), Timeline::align_by_time(&b)];
+
+        let timeline = Timeline::aggregate(TimelineAlignment::Time, 5.0, &runs);
+        assert_eq!(timeline.runs, 2);
+        let memory: Vec<(f64, usize, Band)> = timeline
+            .points
+            .iter()
+            .map(|p| (p.position, p.runs, p.metrics["memory"]))
+            .collect();
+        assert_eq!(
+            memory,
+            vec![
+                (
+                    0.0,
+                    2,
+                    Band {
+                        mean: 200.0,
+                        min: 100.0,
+                        max: 300.0
+                    }
+                ),
+                (
+                    5.0,
+                    2,
+                    Band {
+                        mean: 300.0,
+                        min: 200.0,
+                        max: 400.0
+                    }
+                ),
+                (
+                    10.0,
+                    1,
+                    Band {
+                        mean: 300.0,
+                        min: 300.0,
+                        max: 300.0
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_aggregate_by_height() {
+        let tips = [
+            TipUpdate {
+                time: 1002.0,
+                height: 10,
+                ..Default::default()
+            },
+            TipUpdate {
+                time: 1004.0,
+                height: 25,
+                ..Default::default()
+            },
+        ];
+        // The first sample is taken before any tip update
+        let a = profile(1000.0, &[(1, 100), (3, 200), (4, 300), (9, 500)]);
+        let aligned = Timeline::align_by_height(&a, &tips);
+        assert_eq!(
+            aligned.iter().map(|(h, _)| *h).collect::<Vec<_>>(),
+            vec![10.0, 25.0, 25.0]
+        );
+
+        let timeline = Timeline::aggregate(TimelineAlignment::Height, 10.0, &[aligned]);
+        let memory: Vec<(f64, f64)> = timeline
+            .points
+            .iter()
+            .map(|p| (p.position, p.metrics["memory"].mean))
+            .collect();
+        assert_eq!(memory, vec![(10.0, 200.0), (20.0, 400.0)]);
+    }
+}
@@ -1,11 +1,17 @@
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 
+use crate::path_utils;
+
 /// Default port for Bitcoin Core P2P connections
 pub const DEFAULT_P2P_PORT: u16 = 12000;
 
 /// Default port for Bitcoin Core RPC connections
 pub const DEFAULT_RPC_PORT: u16 = 12001;
 
+/// Datadir subdirectories whose size is tracked when none are configured
+pub const DEFAULT_DATADIR_DIRS: &[&str] = &["blocks", "chainstate", "indexes"];
+
 /// Check if a binary exists for a given commit
 pub fn binary_exists(bin_dir: &Path, commit: &str) -> bool {
     let binary_path = get_binary_path(bin_dir, commit);
@@ -38,6 +44,27 @@ pub fn check_binaries_exist(
     }
 }
 
+/// Get the network-specific data directory bitcoind uses inside `datadir`
+pub fn get_network_datadir(datadir: &Path, network: &str) -> PathBuf {
+    match network {
+        "main" => datadir.to_path_buf(),
+        "test" => datadir.join("testnet3"),
+        _ => datadir.join(network),
+    }
+}
+
+/// Get the size in bytes of each of `dirs` inside a network datadir
+pub fn get_datadir_sizes(network_datadir: &Path, dirs: &[String]) -> BTreeMap<String, u64> {
+    dirs.iter()
+        .map(|dir| {
+            (
+                dir.clone(),
+                path_utils::dir_size(&network_datadir.join(dir)),
+            )
+        })
+        .collect()
+}
+
 /// Build base bitcoind command arguments that are common across all invocations
 pub fn build_bitcoind_base_args(network: &str, datadir: &Path, connect: &str) -> Vec<String> {
     let mut args = vec![
@@ -54,17 +81,20 @@ pub fn build_bitcoind_base_args(network: &str, datadir: &Path, connect: &str) ->
     args
 }
 
-/// Build the full benchmark command with parameter substitution
+/// Build the full benchmark command with parameter substitution, adding
+/// `extra_args` after the base arguments
 pub fn build_benchmark_command(
     bin_dir: &Path,
     commit_placeholder: &str,
     network: &str,
     datadir: &Path,
     connect: &str,
+    extra_args: &[String],
     command_template: &str,
 ) -> String {
     let bitcoind_path = format!("{}/bitcoind-{}", bin_dir.display(), commit_placeholder);
-    let base_args = build_bitcoind_base_args(network, datadir, connect);
+    let mut base_args = build_bitcoind_base_args(network, datadir, connect);
+    base_args.extend_from_slice(extra_args);
     let base_args_str = base_args.join(" ");
     command_template.replace("bitcoind", &format!("{} {}", bitcoind_path, base_args_str))
 }
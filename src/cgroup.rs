@@ -0,0 +1,495 @@
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Controllers enabled for benchmark cgroups
+const CONTROLLERS: &[&str] = &["cpu", "io", "memory"];
+
+/// How long to wait for a killed cgroup to empty before giving up
+const KILL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Period used for `cpu.max`, in microseconds
+const CPU_PERIOD_USEC: u64 = 100_000;
+
+/// Resource limits for a benchmark, as configured
+///
+/// Values may contain `{var}` placeholders, which are filled in from the
+/// parameter matrix so that limits can be varied like any other parameter.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResourceLimits {
+    /// Memory limit, in bytes or with a K/M/G/T suffix (`memory.max`)
+    pub memory_max: Option<String>,
+    /// CPU limit in cores, e.g. "2" or "0.5" (`cpu.max`)
+    pub cpu_quota: Option<String>,
+    /// Read bandwidth limit on the datadir device, in bytes/s or with a suffix
+    pub io_read_bps: Option<String>,
+    /// Write bandwidth limit on the datadir device, in bytes/s or with a suffix
+    pub io_write_bps: Option<String>,
+    /// Read IOPS limit on the datadir device
+    pub io_read_iops: Option<String>,
+    /// Write IOPS limit on the datadir device
+    pub io_write_iops: Option<String>,
+}
+
+/// Resource limits with placeholders filled in and values parsed, as applied
+/// to a run
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AppliedLimits {
+    /// Memory limit in bytes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_max: Option<u64>,
+    /// CPU limit in cores
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_quota: Option<f64>,
+    /// Read bandwidth limit in bytes/s
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub io_read_bps: Option<u64>,
+    /// Write bandwidth limit in bytes/s
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub io_write_bps: Option<u64>,
+    /// Read IOPS limit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub io_read_iops: Option<u64>,
+    /// Write IOPS limit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub io_write_iops: Option<u64>,
+}
+
+impl ResourceLimits {
+    /// Check the values that don't depend on parameters
+    pub fn validate(&self) -> Result<()> {
+        self.resolve_with(|value| (!value.contains('{')).then(|| value.to_string()))
+            .map(|_| ())
+    }
+
+    /// Fill in `{var}` placeholders from `params` and parse the values
+    pub fn resolve(&self, params: &HashMap<String, String>) -> Result<AppliedLimits> {
+        self.resolve_with(|value| {
+            let mut value = value.to_string();
+            for (var, param) in params {
+                value = value.replace(&format!("{{{var}}}"), param);
+            }
+            Some(value)
+        })
+    }
+
+    /// Parse each configured value after passing it through `substitute`,
+    /// skipping values it returns `None` for
+    fn resolve_with(&self, substitute: impl Fn(&str) -> Option<String>) -> Result<AppliedLimits> {
+        let value = |name: &str, field: &Option<String>| -> Result<Option<String>> {
+            let Some(value) = field.as_deref().and_then(&substitute) else {
+                return Ok(None);
+            };
+            if value.contains('{') {
+                anyhow::bail!("Unresolved placeholder in limits.{name}: {value}");
+            }
+            Ok(Some(value))
+        };
+        let size = |name: &str, field: &Option<String>| -> Result<Option<u64>> {
+            value(name, field)?
+                .map(|v| parse_size(&v).with_context(|| format!("Invalid limits.{name}: {v}")))
+                .transpose()
+        };
+        let count = |name: &str, field: &Option<String>| -> Result<Option<u64>> {
+            value(name, field)?
+                .map(|v| {
+                    v.trim()
+                        .parse::<u64>()
+                        .with_context(|| format!("Invalid limits.{name}: {v}"))
+                })
+                .transpose()
+        };
+
+        let cpu_quota = value("cpu_quota", &self.cpu_quota)?
+            .map(|v| match v.trim().parse::<f64>() {
+                Ok(cores) if cores > 0.0 && cores.is_finite() => Ok(cores),
+                _ => Err(anyhow::anyhow!(
+                    "Invalid limits.cpu_quota: {v} (expected a positive number of cores)"
+                )),
+            })
+            .transpose()?;
+
+        Ok(AppliedLimits {
+            memory_max: size("memory_max", &self.memory_max)?,
+            cpu_quota,
+            io_read_bps: size("io_read_bps", &self.io_read_bps)?,
+            io_write_bps: size("io_write_bps", &self.io_write_bps)?,
+            io_read_iops: count("io_read_iops", &self.io_read_iops)?,
+            io_write_iops: count("io_write_iops", &self.io_write_iops)?,
+        })
+    }
+}
+
+impl AppliedLimits {
+    /// Whether any IO limit is set
+    pub fn has_io_limits(&self) -> bool {
+        self.io_read_bps.is_some()
+            || self.io_write_bps.is_some()
+            || self.io_read_iops.is_some()
+            || self.io_write_iops.is_some()
+    }
+}
+
+/// Resource accounting read from a cgroup at the end of a run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CgroupStats {
+    /// Peak memory usage of the whole cgroup in bytes (`memory.peak`)
+    pub memory_peak: u64,
+    /// Counters from `memory.stat`
+    pub memory_stat: BTreeMap<String, u64>,
+    /// Counters from `io.stat`, keyed by `major:minor` device
+    pub io_stat: BTreeMap<String, BTreeMap<String, u64>>,
+    /// Counters from `cpu.stat`
+    pub cpu_stat: BTreeMap<String, u64>,
+}
+
+/// A transient cgroup v2 that benchmark commands are placed in
+///
+/// The cgroup is created under a delegated subtree and removed again when
+/// dropped, killing any process still inside it.
+#[derive(Debug)]
+pub struct Cgroup {
+    path: PathBuf,
+    removed: bool,
+}
+
+impl Cgroup {
+    /// Create a cgroup called `name` under the delegated `root`
+    ///
+    /// A stale cgroup of the same name left behind by an earlier crash is
+    /// torn down first.
+    pub fn create(root: &Path, name: &str) -> Result<Self> {
+        enable_controllers(root);
+
+        let path = root.join(name);
+        if path.exists() {
+            debug!("Removing stale cgroup {}", path.display());
+            Cgroup {
+                path: path.clone(),
+                removed: false,
+            }
+            .teardown()?;
+        }
+
+        fs::create_dir(&path)
+            .with_context(|| format!("Failed to create cgroup: {}", path.display()))?;
+        debug!("Created cgroup {}", path.display());
+
+        Ok(Self {
+            path,
+            removed: false,
+        })
+    }
+
+    /// Path of the cgroup directory
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Apply resource limits to the cgroup
+    ///
+    /// IO limits are applied to `device`, which must be the block device
+    /// backing the datadir.
+    pub fn apply_limits(&self, limits: &AppliedLimits, device: Option<(u32, u32)>) -> Result<()> {
+        let write = |file: &str, value: String| {
+            debug!("Setting {file} = {value} in {}", self.path.display());
+            fs::write(self.path.join(file), &value).with_context(|| {
+                format!(
+                    "Failed to set {file} to '{value}' in {}, is the controller enabled?",
+                    self.path.display()
+                )
+            })
+        };
+
+        if let Some(bytes) = limits.memory_max {
+            write("memory.max", bytes.to_string())?;
+        }
+        if let Some(cores) = limits.cpu_quota {
+            let quota = (cores * CPU_PERIOD_USEC as f64).round() as u64;
+            write("cpu.max", format!("{quota} {CPU_PERIOD_USEC}"))?;
+        }
+        if limits.has_io_limits() {
+            let (major, minor) = device
+                .and_then(|(major, minor)| whole_disk(major, minor))
+                .context("IO limits require the datadir to be on a block device")?;
+            let mut line = format!("{major}:{minor}");
+            for (key, value) in [
+                ("rbps", limits.io_read_bps),
+                ("wbps", limits.io_write_bps),
+                ("riops", limits.io_read_iops),
+                ("wiops", limits.io_write_iops),
+            ] {
+                if let Some(value) = value {
+                    line.push_str(&format!(" {key}={value}"));
+                }
+            }
+            write("io.max", line)?;
+        }
+
+        Ok(())
+    }
+
+    /// Read the resource accounting files of the cgroup
+    ///
+    /// Files that are missing (e.g. `memory.peak` on kernels before 5.19, or a
+    /// controller that could not be enabled) leave their fields empty.
+    pub fn stats(&self) -> CgroupStats {
+        let read = |file: &str| fs::read_to_string(self.path.join(file)).unwrap_or_default();
+
+        CgroupStats {
+            memory_peak: read("memory.peak").trim().parse().unwrap_or(0),
+            memory_stat: parse_flat_keyed(&read("memory.stat")),
+            io_stat: parse_io_stat(&read("io.stat")),
+            cpu_stat: parse_flat_keyed(&read("cpu.stat")),
+        }
+    }
+
+    /// Kill every process in the cgroup and wait for it to empty
+    pub fn kill(&self) -> Result<()> {
+        if self.pids().is_empty() {
+            return Ok(());
+        }
+
+        // `cgroup.kill` (5.14+) kills the whole subtree atomically, including
+        // processes forked while the kill is in progress
+        if fs::write(self.path.join("cgroup.kill"), "1").is_err() {
+            for pid in self.pids() {
+                unsafe {
+                    libc::kill(pid as i32, libc::SIGKILL);
+                }
+            }
+        }
+
+        let start = Instant::now();
+        loop {
+            let remaining = self.pids();
+            if remaining.is_empty() {
+                return Ok(());
+            }
+            if start.elapsed() > KILL_TIMEOUT {
+                anyhow::bail!(
+                    "Processes {:?} still running in cgroup {} after kill",
+                    remaining,
+                    self.path.display()
+                );
+            }
+            for pid in remaining {
+                unsafe {
+                    libc::kill(pid as i32, libc::SIGKILL);
+                }
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    /// Kill any remaining processes and remove the cgroup
+    pub fn teardown(mut self) -> Result<()> {
+        self.remove()
+    }
+
+    fn remove(&mut self) -> Result<()> {
+        if self.removed {
+            return Ok(());
+        }
+        self.kill()?;
+        fs::remove_dir(&self.path)
+            .with_context(|| format!("Failed to remove cgroup: {}", self.path.display()))?;
+        self.removed = true;
+        debug!("Removed cgroup {}", self.path.display());
+        Ok(())
+    }
+
+    /// PIDs of all processes currently in the cgroup
+    fn pids(&self) -> Vec<u32> {
+        fs::read_to_string(self.path.join("cgroup.procs"))
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| line.trim().parse().ok())
+            .collect()
+    }
+}
+
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        if let Err(e) = self.remove() {
+            warn!("Failed to tear down cgroup: {e}");
+        }
+    }
+}
+
+/// Check that `path` is a directory in a cgroup v2 hierarchy
+pub fn is_cgroup_dir(path: &Path) -> bool {
+    path.join("cgroup.procs").is_file() && path.join("cgroup.subtree_control").is_file()
+}
+
+/// Enable the accounting controllers for children of `root`
+///
+/// Failures are logged rather than returned, as the cgroup still provides
+/// reliable teardown without them.
+fn enable_controllers(root: &Path) {
+    let available = fs::read_to_string(root.join("cgroup.controllers")).unwrap_or_default();
+    let enabled = fs::read_to_string(root.join("cgroup.subtree_control")).unwrap_or_default();
+
+    for controller in CONTROLLERS {
+        if enabled.split_whitespace().any(|c| c == *controller) {
+            continue;
+        }
+        if !available.split_whitespace().any(|c| c == *controller) {
+            warn!(
+                "cgroup controller '{controller}' is not delegated to {}, its stats will be missing",
+                root.display()
+            );
+            continue;
+        }
+        if let Err(e) = fs::write(
+            root.join("cgroup.subtree_control"),
+            format!("+{controller}"),
+        ) {
+            warn!(
+                "Failed to enable cgroup controller '{controller}' in {}: {e}",
+                root.display()
+            );
+        }
+    }
+}
+
+/// Resolve a partition to the disk it is on, as `io.max` only accepts whole
+/// disks
+fn whole_disk(major: u32, minor: u32) -> Option<(u32, u32)> {
+    let sys = PathBuf::from(format!("/sys/dev/block/{major}:{minor}"));
+    if !sys.join("partition").exists() {
+        return Some((major, minor));
+    }
+
+    let disk = fs::canonicalize(&sys).ok()?.parent()?.join("dev");
+    let (major, minor) = fs::read_to_string(disk)
+        .ok()?
+        .trim()
+        .split_once(':')
+        .map(|(a, b)| (a.parse().ok(), b.parse().ok()))?;
+    Some((major?, minor?))
+}
+
+/// Parse a size in bytes with an optional binary K/M/G/T suffix
+pub fn parse_size(value: &str) -> Result<u64> {
+    let value = value.trim();
+    let (number, multiplier) = match value.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => {
+            let shift = match c.to_ascii_uppercase() {
+                'K' => 10,
+                'M' => 20,
+                'G' => 30,
+                'T' => 40,
+                _ => anyhow::bail!("Unknown size suffix: {c}"),
+            };
+            (&value[..i], 1u64 << shift)
+        }
+        _ => (value, 1),
+    };
+
+    let number: f64 = number.trim().parse().context("Invalid size")?;
+    if !(number.is_finite() && number > 0.0) {
+        anyhow::bail!("Size must be positive");
+    }
+    Ok((number * multiplier as f64).round() as u64)
+}
+
+/// Parse a flat keyed file such as `memory.stat` or `cpu.stat`
+pub fn parse_flat_keyed(contents: &str) -> BTreeMap<String, u64> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(' ')?;
+            Some((key.to_string(), value.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+/// Parse `io.stat`, which has one line per device of `key=value` pairs
+pub fn parse_io_stat(contents: &str) -> BTreeMap<String, BTreeMap<String, u64>> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let device = parts.next()?.to_string();
+            let counters = parts
+                .filter_map(|pair| {
+                    let (key, value) = pair.split_once('=')?;
+                    Some((key.to_string(), value.parse().ok()?))
+                })
+                .collect();
+            Some((device, counters))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_flat_keyed() {
+        let stats = parse_flat_keyed(
+            "usage_usec 1500\nuser_usec 1000\nsystem_usec 500\nnr_throttled 0\nbogus\n",
+        );
+        assert_eq!(stats.get("usage_usec"), Some(&1500));
+        assert_eq!(stats.get("system_usec"), Some(&500));
+        assert_eq!(stats.get("nr_throttled"), Some(&0));
+        assert_eq!(stats.len(), 4);
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("4096").unwrap(), 4096);
+        assert_eq!(parse_size("4G").unwrap(), 4 << 30);
+        assert_eq!(parse_size("1.5m").unwrap(), 3 << 19);
+        assert!(parse_size("4X").is_err());
+        assert!(parse_size("0").is_err());
+        assert!(parse_size("").is_err());
+    }
+
+    #[test]
+    fn test_resolve_limits() {
+        let limits = ResourceLimits {
+            memory_max: Some("{mem}".to_string()),
+            cpu_quota: Some("2".to_string()),
+            io_write_bps: Some("100M".to_string()),
+            ..Default::default()
+        };
+        assert!(limits.validate().is_ok());
+
+        let params = HashMap::from([("mem".to_string(), "4G".to_string())]);
+        let applied = limits.resolve(&params).unwrap();
+        assert_eq!(applied.memory_max, Some(4 << 30));
+        assert_eq!(applied.cpu_quota, Some(2.0));
+        assert_eq!(applied.io_write_bps, Some(100 << 20));
+        assert!(applied.has_io_limits());
+
+        // Placeholders that aren't parameters are an error
+        assert!(limits.resolve(&HashMap::new()).is_err());
+
+        let invalid = ResourceLimits {
+            cpu_quota: Some("-1".to_string()),
+            ..Default::default()
+        };
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn test_parse_io_stat() {
+        let stats = parse_io_stat(
+            "8:16 rbytes=1459200 wbytes=314773504 rios=192 wios=353 dbytes=0 dios=0\n\
+             8:0 rbytes=90430464 wbytes=299008000 rios=8950 wios=1252 dbytes=50331648 dios=3021\n",
+        );
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats["8:16"]["wbytes"], 314773504);
+        assert_eq!(stats["8:0"]["rios"], 8950);
+        assert_eq!(stats["8:0"]["dios"], 3021);
+    }
+}
@@ -4,8 +4,10 @@ use log::warn;
 use log::{debug, info};
 use std::collections::HashMap;
 use std::fmt::Debug;
+use std::fs::OpenOptions;
+use std::os::unix::io::AsRawFd;
 use std::os::unix::process::CommandExt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::{Child, Command, ExitStatus, Output, Stdio};
 
 #[cfg(target_os = "linux")]
@@ -28,6 +30,8 @@ pub struct CommandContext {
     pub capture_output: bool,
     /// Allow command to fail without returning an error
     pub allow_failure: bool,
+    /// cgroup v2 directory to place the command in before it execs
+    pub cgroup: Option<PathBuf>,
 }
 
 /// Builder for CommandExecutor
@@ -91,6 +95,12 @@ impl CommandExecutorBuilder {
         self
     }
 
+    /// Place the command in a cgroup v2 directory before it starts
+    pub fn cgroup<P: AsRef<Path>>(mut self, cgroup: Option<P>) -> Self {
+        self.context.cgroup = cgroup.map(|c| c.as_ref().to_path_buf());
+        self
+    }
+
     /// Set a name for the command for logging purposes
     pub fn name(mut self, name: impl Into<String>) -> Self {
         self.context.command_name = Some(name.into());
@@ -216,10 +226,36 @@ impl CommandExecutor {
             command.process_group(0);
         }
 
+        // Join the cgroup between fork and exec, so that every descendant is
+        // accounted to it from the start. The file is opened here as only
+        // async-signal-safe calls are allowed in the child.
+        let cgroup_procs = match &self.context.cgroup {
+            Some(cgroup) => {
+                let procs = cgroup.join("cgroup.procs");
+                let file = OpenOptions::new()
+                    .write(true)
+                    .open(&procs)
+                    .with_context(|| format!("Failed to open {}", procs.display()))?;
+                let fd = file.as_raw_fd();
+                // Writing "0" moves the writing process into the cgroup
+                unsafe {
+                    command.pre_exec(move || {
+                        if libc::write(fd, b"0".as_ptr().cast(), 1) < 0 {
+                            return Err(std::io::Error::last_os_error());
+                        }
+                        Ok(())
+                    });
+                }
+                Some(file)
+            }
+            None => None,
+        };
+
         // Spawn the command
         let child = command
             .spawn()
             .with_context(|| format!("Failed to spawn command: {command_str}"))?;
+        drop(cgroup_procs);
 
         // Apply CPU affinity if specified
         if let Some(cores) = &self.context.cpu_cores {
@@ -320,6 +356,7 @@ impl CommandExecutor {
             env_vars: options.env_vars.clone(),
             allow_failure: options.allow_failure,
             command_name: options.command_name.clone(),
+            cgroup: None,
         }
     }
 }
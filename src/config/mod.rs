@@ -5,6 +5,9 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::{collections::HashMap, path::PathBuf};
 
+use crate::benchmarks::{LogRule, MeasureMarker, PeerConfig, PruneConfig, ScriptHooks};
+use crate::cgroup::ResourceLimits;
+use crate::cpu_binding::parse_cores_list;
 use crate::path_utils;
 
 /// Application configuration loaded from config.yml
@@ -33,6 +36,23 @@ pub struct BenchmarkOptions {
     pub profile_interval: Option<u64>,
     pub stop_on_log_pattern: Option<String>,
     pub perf_instrumentation: Option<bool>,
+    pub datadir_dirs: Option<Vec<String>>,
+    pub interference_threshold: Option<f64>,
+    pub stall_timeout: Option<u64>,
+    pub stall_cpu_threshold: Option<f64>,
+    pub stall_io_threshold: Option<u64>,
+    pub timeout: Option<u64>,
+    pub timeline_alignment: Option<String>,
+    pub timeline_bucket: Option<u64>,
+    pub milestones: Option<Vec<u64>>,
+    pub validation_timing: Option<bool>,
+    pub log_rules: Option<Vec<LogRule>>,
+    pub rpc_metrics: Option<bool>,
+    pub rpc_interval: Option<u64>,
+    pub verify_chain_state: Option<bool>,
+    pub stop_at_height: Option<u64>,
+    pub measure_from: Option<MeasureMarker>,
+    pub measure_to: Option<MeasureMarker>,
 }
 
 fn default_warmup() -> usize {
@@ -61,6 +81,23 @@ impl BenchmarkOptions {
             profile_interval: None,
             stop_on_log_pattern: None,
             perf_instrumentation: None,
+            datadir_dirs: None,
+            interference_threshold: None,
+            stall_timeout: None,
+            stall_cpu_threshold: None,
+            stall_io_threshold: None,
+            timeout: None,
+            timeline_alignment: None,
+            timeline_bucket: None,
+            milestones: None,
+            validation_timing: None,
+            log_rules: None,
+            rpc_metrics: None,
+            rpc_interval: None,
+            verify_chain_state: None,
+            stop_at_height: None,
+            measure_from: None,
+            measure_to: None,
         }
     }
 
@@ -81,6 +118,54 @@ impl BenchmarkOptions {
             }
         }
 
+        for rule in self.log_rules.iter().flatten() {
+            rule.validate()?;
+        }
+
+        if let Some(dirs) = &self.datadir_dirs {
+            if dirs.iter().any(|d| d.is_empty() || d.contains("..")) {
+                anyhow::bail!("datadir_dirs entries must be non-empty paths inside the datadir");
+            }
+        }
+
+        if let Some(threshold) = self.interference_threshold {
+            if threshold.is_nan() || threshold < 0.0 {
+                anyhow::bail!("interference_threshold must be a non-negative percentage");
+            }
+        }
+
+        if self.timeout == Some(0) {
+            anyhow::bail!("timeout cannot be zero");
+        }
+
+        if let Some(alignment) = &self.timeline_alignment {
+            crate::benchmarks::TimelineAlignment::alignment_from_str(alignment)?;
+        }
+
+        if self.timeline_bucket == Some(0) {
+            anyhow::bail!("timeline_bucket cannot be zero");
+        }
+
+        for marker in [&self.measure_from, &self.measure_to].into_iter().flatten() {
+            if let MeasureMarker::Log(pattern) = marker {
+                Regex::new(pattern).context("Invalid regex pattern in measurement marker")?;
+            }
+        }
+
+        if self.stop_at_height == Some(0) {
+            anyhow::bail!("stop_at_height cannot be zero");
+        }
+
+        if self.rpc_interval == Some(0) {
+            anyhow::bail!("rpc_interval cannot be zero");
+        }
+
+        if let Some(threshold) = self.stall_cpu_threshold {
+            if threshold.is_nan() || threshold < 0.0 {
+                anyhow::bail!("stall_cpu_threshold must be a non-negative percentage");
+            }
+        }
+
         // Validate perf instrumentation is only enabled on Linux
         if let Some(true) = self.perf_instrumentation {
             #[cfg(not(target_os = "linux"))]
@@ -141,6 +226,82 @@ impl BenchmarkOptions {
             result.perf_instrumentation = Some(perf_instrumentation);
         }
 
+        if let Some(datadir_dirs) = map.get("datadir_dirs").and_then(|v| v.as_array()) {
+            result.datadir_dirs = Some(
+                datadir_dirs
+                    .iter()
+                    .filter_map(|v| v.as_str().map(|s| s.to_string()))
+                    .collect(),
+            );
+        }
+
+        if let Some(threshold) = map.get("interference_threshold").and_then(|v| v.as_f64()) {
+            result.interference_threshold = Some(threshold);
+        }
+
+        if let Some(timeout) = map.get("stall_timeout").and_then(|v| v.as_u64()) {
+            result.stall_timeout = Some(timeout);
+        }
+
+        if let Some(threshold) = map.get("stall_cpu_threshold").and_then(|v| v.as_f64()) {
+            result.stall_cpu_threshold = Some(threshold);
+        }
+
+        if let Some(threshold) = map.get("stall_io_threshold").and_then(|v| v.as_u64()) {
+            result.stall_io_threshold = Some(threshold);
+        }
+
+        if let Some(timeout) = map.get("timeout").and_then(|v| v.as_u64()) {
+            result.timeout = Some(timeout);
+        }
+
+        if let Some(alignment) = map.get("timeline_alignment").and_then(|v| v.as_str()) {
+            result.timeline_alignment = Some(alignment.to_string());
+        }
+
+        if let Some(bucket) = map.get("timeline_bucket").and_then(|v| v.as_u64()) {
+            result.timeline_bucket = Some(bucket);
+        }
+
+        if let Some(milestones) = map.get("milestones").and_then(|v| v.as_array()) {
+            result.milestones = Some(milestones.iter().filter_map(|v| v.as_u64()).collect());
+        }
+
+        if let Some(validation_timing) = map.get("validation_timing").and_then(|v| v.as_bool()) {
+            result.validation_timing = Some(validation_timing);
+        }
+
+        if let Some(rules) = map.get("log_rules") {
+            result.log_rules =
+                Some(serde_json::from_value(rules.clone()).context("Invalid log_rules")?);
+        }
+
+        if let Some(rpc_metrics) = map.get("rpc_metrics").and_then(|v| v.as_bool()) {
+            result.rpc_metrics = Some(rpc_metrics);
+        }
+
+        if let Some(interval) = map.get("rpc_interval").and_then(|v| v.as_u64()) {
+            result.rpc_interval = Some(interval);
+        }
+
+        if let Some(verify) = map.get("verify_chain_state").and_then(|v| v.as_bool()) {
+            result.verify_chain_state = Some(verify);
+        }
+
+        if let Some(height) = map.get("stop_at_height").and_then(|v| v.as_u64()) {
+            result.stop_at_height = Some(height);
+        }
+
+        if let Some(marker) = map.get("measure_from") {
+            result.measure_from =
+                Some(serde_json::from_value(marker.clone()).context("Invalid measure_from")?);
+        }
+
+        if let Some(marker) = map.get("measure_to") {
+            result.measure_to =
+                Some(serde_json::from_value(marker.clone()).context("Invalid measure_to")?);
+        }
+
         Ok(result)
     }
 }
@@ -151,6 +312,7 @@ pub struct BenchmarkGlobalConfig {
     pub benchmark: Option<BenchmarkOptions>,
     pub benchmark_cores: Option<String>,
     pub runner_cores: Option<String>,
+    pub cgroup_root: Option<PathBuf>,
     pub cmake_build_args: Option<Vec<String>>,
     pub source: PathBuf,
     pub scratch: PathBuf,
@@ -167,6 +329,32 @@ pub struct SingleConfig {
     pub connect: Option<String>,
     #[serde(default)]
     pub mode: Option<String>,
+    #[serde(default)]
+    pub limits: Option<ResourceLimits>,
+    #[serde(default)]
+    pub hooks: Option<ScriptHooks>,
+    /// Pre-synced datadir restored before each run in datadir mode
+    #[serde(default)]
+    pub source_datadir: Option<PathBuf>,
+    /// Commit whose binary creates the datadir each run upgrades from, in
+    /// upgrade mode
+    #[serde(default)]
+    pub upgrade_from: Option<String>,
+    /// Height the upgrade_from binary syncs to, when no source_datadir is set
+    #[serde(default)]
+    pub upgrade_height: Option<u64>,
+    /// Datadir whose block files are staged in reindex modes
+    #[serde(default)]
+    pub block_source: Option<PathBuf>,
+    /// Block files, or directories of blk*.dat files, imported in loadblock mode
+    #[serde(default)]
+    pub load_blocks: Option<Vec<PathBuf>>,
+    /// Peer benchkit starts to serve blocks, instead of `connect`
+    #[serde(default)]
+    pub peer: Option<PeerConfig>,
+    /// Prune target and how runs are prepared, in pruned mode
+    #[serde(default)]
+    pub prune: Option<PruneConfig>,
     pub benchmark: HashMap<String, Value>,
 }
 
@@ -179,6 +367,24 @@ pub struct BenchmarkConfig {
     pub path: PathBuf,
 }
 
+impl BenchmarkConfig {
+    /// Commits that need a binary: the benchmarked commits followed by any
+    /// commits benchmarks upgrade from
+    pub fn required_commits(&self) -> Vec<String> {
+        let mut commits = self.global.commits.clone();
+        for from in self
+            .benchmarks
+            .iter()
+            .filter_map(|b| b.upgrade_from.as_ref())
+        {
+            if !commits.contains(from) {
+                commits.push(from.clone());
+            }
+        }
+        commits
+    }
+}
+
 /// Global configuration containing both app and benchmark configurations
 #[derive(Debug, Clone)]
 pub struct GlobalConfig {
@@ -271,6 +477,26 @@ pub fn load_bench_config(bench_config_path: &PathBuf) -> Result<BenchmarkConfig>
         )?;
     }
 
+    // Source datadirs must already exist, so are not created
+    for benchmark in &mut config.benchmarks {
+        if let Some(source) = &mut benchmark.source_datadir {
+            path_utils::process_paths(&mut [source], config_dir, false)?;
+        }
+        if let Some(source) = &mut benchmark.block_source {
+            path_utils::process_paths(&mut [source], config_dir, false)?;
+        }
+        for file in benchmark.load_blocks.iter_mut().flatten() {
+            path_utils::process_paths(&mut [file], config_dir, false)?;
+        }
+        if let Some(peer) = &mut benchmark.peer {
+            path_utils::process_paths(
+                &mut [&mut peer.binary, &mut peer.datadir],
+                config_dir,
+                false,
+            )?;
+        }
+    }
+
     validate_config(&config)?;
 
     debug!(
@@ -327,6 +553,12 @@ fn validate_config(config: &BenchmarkConfig) -> Result<()> {
         }
     }
 
+    if let Some(root) = &config.global.cgroup_root {
+        if !root.is_absolute() {
+            anyhow::bail!("cgroup_root must be an absolute path: {}", root.display());
+        }
+    }
+
     // Validate benchmarks
     if config.benchmarks.is_empty() {
         anyhow::bail!("No benchmarks configured");
@@ -337,20 +569,168 @@ fn validate_config(config: &BenchmarkConfig) -> Result<()> {
             anyhow::bail!("Benchmark name cannot be empty");
         }
 
+        if let Some(limits) = &benchmark.limits {
+            if config.global.cgroup_root.is_none() {
+                anyhow::bail!(
+                    "Benchmark '{}' sets limits, which require cgroup_root to be configured",
+                    benchmark.name
+                );
+            }
+            limits
+                .validate()
+                .with_context(|| format!("Invalid limits for benchmark '{}'", benchmark.name))?;
+        }
+
         match benchmark.network.as_str() {
             "main" | "test" | "signet" | "regtest" => {}
             _ => anyhow::bail!("Invalid network type: {}", benchmark.network),
         }
 
-        if let Some(mode) = &benchmark.mode {
-            use crate::benchmarks::HookMode;
-            HookMode::mode_from_str(mode)?;
+        if let Some(peer) = &benchmark.peer {
+            validate_peer(config, benchmark, peer)?;
+        }
+
+        use crate::benchmarks::HookMode;
+        let mode = match &benchmark.mode {
+            Some(mode) => HookMode::mode_from_str(mode)?,
+            None => HookMode::default(),
+        };
+        let loads_snapshot = match (&benchmark.prune, mode) {
+            (_, HookMode::AssumeUtxo) => true,
+            (Some(prune), HookMode::Pruned) => prune.prepare_mode()? == HookMode::AssumeUtxo,
+            _ => false,
+        };
+        if loads_snapshot {
+            validate_milestones(config, benchmark)?;
+        }
+        if mode == HookMode::Datadir && benchmark.source_datadir.is_none() {
+            anyhow::bail!(
+                "Benchmark '{}' uses datadir mode, which requires source_datadir",
+                benchmark.name
+            );
+        }
+        let reindex = matches!(mode, HookMode::Reindex | HookMode::ReindexChainstate);
+        if reindex && benchmark.block_source.is_none() {
+            anyhow::bail!(
+                "Benchmark '{}' uses a reindex mode, which requires block_source",
+                benchmark.name
+            );
+        }
+        let load_blocks = benchmark
+            .load_blocks
+            .as_ref()
+            .is_some_and(|f| !f.is_empty());
+        if mode == HookMode::LoadBlock && !load_blocks {
+            anyhow::bail!(
+                "Benchmark '{}' uses loadblock mode, which requires load_blocks",
+                benchmark.name
+            );
+        }
+        match (&benchmark.prune, mode) {
+            (Some(prune), HookMode::Pruned) => prune
+                .validate()
+                .with_context(|| format!("Invalid prune for benchmark '{}'", benchmark.name))?,
+            (None, HookMode::Pruned) => anyhow::bail!(
+                "Benchmark '{}' uses pruned mode, which requires prune",
+                benchmark.name
+            ),
+            (Some(_), _) => anyhow::bail!(
+                "Benchmark '{}' sets prune without using pruned mode",
+                benchmark.name
+            ),
+            (None, _) => {}
+        }
+        if mode == HookMode::Upgrade {
+            if benchmark.upgrade_from.is_none() {
+                anyhow::bail!(
+                    "Benchmark '{}' uses upgrade mode, which requires upgrade_from",
+                    benchmark.name
+                );
+            }
+            if benchmark.source_datadir.is_none() && benchmark.upgrade_height.is_none() {
+                anyhow::bail!(
+                    "Benchmark '{}' uses upgrade mode, which requires upgrade_height or source_datadir",
+                    benchmark.name
+                );
+            }
+        } else if benchmark.upgrade_from.is_some() || benchmark.upgrade_height.is_some() {
+            anyhow::bail!(
+                "Benchmark '{}' sets upgrade_from or upgrade_height without using upgrade mode",
+                benchmark.name
+            );
         }
     }
 
     Ok(())
 }
 
+/// Reject milestones a run reaches as soon as it loads the snapshot, which
+/// would be recorded at the time the snapshot was loaded
+fn validate_milestones(config: &BenchmarkConfig, benchmark: &SingleConfig) -> Result<()> {
+    use crate::download::SnapshotInfo;
+    use crate::types::Network;
+    use clap::ValueEnum;
+
+    let options = merge_benchmark_options(&config.global.benchmark, &benchmark.benchmark)?;
+    let snapshot = Network::from_str(&benchmark.network, true)
+        .ok()
+        .and_then(|network| SnapshotInfo::for_network(&network));
+    if let (Some(milestones), Some(snapshot)) = (options.milestones, snapshot) {
+        if let Some(height) = milestones
+            .iter()
+            .find(|&&height| height <= u64::from(snapshot.height))
+        {
+            anyhow::bail!(
+                "Benchmark '{}' has milestone {height}, which is not above the snapshot height {}",
+                benchmark.name,
+                snapshot.height
+            );
+        }
+    }
+    Ok(())
+}
+
+fn validate_peer(
+    config: &BenchmarkConfig,
+    benchmark: &SingleConfig,
+    peer: &PeerConfig,
+) -> Result<()> {
+    if benchmark.connect.is_some() {
+        anyhow::bail!("Benchmark '{}' sets both connect and peer", benchmark.name);
+    }
+    if !peer.datadir.is_dir() {
+        anyhow::bail!("Peer datadir does not exist: {}", peer.datadir.display());
+    }
+
+    let Some(cores) = peer.cores.as_ref().or(config.global.runner_cores.as_ref()) else {
+        // An unpinned peer could be scheduled on the benchmark cores
+        if config.global.benchmark_cores.is_some() {
+            anyhow::bail!(
+                "Benchmark '{}' needs peer cores or runner_cores to keep its peer off benchmark_cores",
+                benchmark.name
+            );
+        }
+        return Ok(());
+    };
+    if !is_valid_cpu_cores(cores) {
+        anyhow::bail!("Invalid peer cores format: {}", cores);
+    }
+    if let Some(benchmark_cores) = &config.global.benchmark_cores {
+        let benchmark_cores = parse_cores_list(benchmark_cores)?;
+        if parse_cores_list(cores)?
+            .iter()
+            .any(|core| benchmark_cores.contains(core))
+        {
+            anyhow::bail!(
+                "Benchmark '{}' pins its peer to cores {} overlapping benchmark_cores",
+                benchmark.name,
+                cores
+            );
+        }
+    }
+    Ok(())
+}
+
 fn is_valid_cpu_cores(cores: &str) -> bool {
     for part in cores.split(',') {
         if part.contains('-') {
@@ -400,6 +780,23 @@ mod tests {
             profile_interval: Some(5),
             stop_on_log_pattern: None,
             perf_instrumentation: None,
+            datadir_dirs: None,
+            interference_threshold: None,
+            stall_timeout: None,
+            stall_cpu_threshold: None,
+            stall_io_threshold: None,
+            timeout: None,
+            timeline_alignment: None,
+            timeline_bucket: None,
+            milestones: None,
+            validation_timing: None,
+            log_rules: None,
+            rpc_metrics: None,
+            rpc_interval: None,
+            verify_chain_state: None,
+            stop_at_height: None,
+            measure_from: None,
+            measure_to: None,
         };
 
         let mut override_map = HashMap::new();
@@ -442,4 +839,118 @@ mod tests {
         assert!(config.snapshot_dir.is_absolute());
         assert_eq!(config.path, config_path);
     }
+
+    #[test]
+    fn test_upgrade_config() {
+        let config = |mode: &str, upgrade: &str| -> BenchmarkConfig {
+            serde_yaml::from_str(&format!(
+                r#"
+                global:
+                  source: /src
+                  scratch: /scratch
+                  commits: [new, old]
+                  tmp_data_dir: /data
+                benchmarks:
+                  - name: upgrade
+                    network: signet
+                    mode: {mode}
+                    {upgrade}
+                    benchmark:
+                      command: bitcoind
+                "#
+            ))
+            .unwrap()
+        };
+
+        let upgrade = config(
+            "upgrade",
+            "upgrade_from: older\n                    upgrade_height: 1000",
+        );
+        validate_config(&upgrade).unwrap();
+        assert_eq!(upgrade.required_commits(), vec!["new", "old", "older"]);
+
+        let already_benchmarked = config(
+            "upgrade",
+            "upgrade_from: old\n                    upgrade_height: 1000",
+        );
+        assert_eq!(already_benchmarked.required_commits(), vec!["new", "old"]);
+
+        // The datadir to upgrade needs a height to sync to or a source
+        assert!(validate_config(&config("upgrade", "upgrade_from: older")).is_err());
+        assert!(validate_config(&config("upgrade", "upgrade_height: 1000")).is_err());
+        assert!(validate_config(&config("full_ibd", "upgrade_from: older")).is_err());
+    }
+
+    #[test]
+    fn test_peer_cores() {
+        let datadir = tempfile::tempdir().unwrap();
+        let config = |cores: &str, peer_cores: &str| -> BenchmarkConfig {
+            serde_yaml::from_str(&format!(
+                r#"
+                global:
+                  source: /src
+                  scratch: /scratch
+                  commits: [new]
+                  tmp_data_dir: /data
+                  {cores}
+                benchmarks:
+                  - name: peer
+                    network: signet
+                    mode: full_ibd
+                    peer:
+                      binary: /bin/bitcoind
+                      datadir: {}
+                      {peer_cores}
+                    benchmark:
+                      command: bitcoind
+                "#,
+                datadir.path().display()
+            ))
+            .unwrap()
+        };
+
+        validate_config(&config("", "")).unwrap();
+        validate_config(&config("benchmark_cores: 2-7", "cores: 0-1")).unwrap();
+        validate_config(&config(
+            "benchmark_cores: 2-7\n                  runner_cores: 0-1",
+            "",
+        ))
+        .unwrap();
+        assert!(validate_config(&config("benchmark_cores: 2-7", "cores: 1-2")).is_err());
+        // Unpinned, the peer could run on the benchmark cores
+        let err = validate_config(&config("benchmark_cores: 2-7", "")).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("keep its peer off benchmark_cores"));
+    }
+
+    #[test]
+    fn test_snapshot_milestones() {
+        let config = |mode: &str, milestones: &str| -> BenchmarkConfig {
+            serde_yaml::from_str(&format!(
+                r#"
+                global:
+                  source: /src
+                  scratch: /scratch
+                  commits: [new]
+                  tmp_data_dir: /data
+                benchmarks:
+                  - name: milestones
+                    network: signet
+                    mode: {mode}
+                    benchmark:
+                      command: bitcoind
+                      milestones: {milestones}
+                "#
+            ))
+            .unwrap()
+        };
+
+        // The signet snapshot is at height 160000
+        validate_config(&config("assumeutxo", "[170000, 180000]")).unwrap();
+        let err = validate_config(&config("assumeutxo", "[150000, 170000]")).unwrap_err();
+        assert!(err.to_string().contains("milestone 150000"));
+        assert!(validate_config(&config("assumeutxo", "[160000]")).is_err());
+        validate_config(&config("full_ibd", "[150000]")).unwrap();
+    }
 }
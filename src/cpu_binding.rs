@@ -57,35 +57,9 @@ impl CpuBinder {
     /// Roughyl mirrors taskset syntax
     fn parse_cores_spec(&self, cores_spec: &str) -> Result<CpuSet> {
         let mut cpuset = CpuSet::new();
-
-        // Split by commas
-        for part in cores_spec.split(',') {
-            if part.contains('-') {
-                // Handle ranges like "0-3"
-                let range: Vec<&str> = part.split('-').collect();
-                if range.len() != 2 {
-                    anyhow::bail!("Invalid core range specification: {}", part);
-                }
-
-                let start = range[0]
-                    .parse::<u32>()
-                    .with_context(|| format!("Invalid core number: {}", range[0]))?;
-                let end = range[1]
-                    .parse::<u32>()
-                    .with_context(|| format!("Invalid core number: {}", range[1]))?;
-
-                for core in start..=end {
-                    cpuset.set(core);
-                }
-            } else {
-                // Handle single core like "5"
-                let core = part
-                    .parse::<u32>()
-                    .with_context(|| format!("Invalid core number: {part}"))?;
-                cpuset.set(core);
-            }
+        for core in parse_cores_list(cores_spec)? {
+            cpuset.set(core);
         }
-
         Ok(cpuset)
     }
 
@@ -209,3 +183,36 @@ impl CpuBinder {
         Ok(())
     }
 }
+
+/// Parse a core specification string (e.g. "0-3,5,7-9") into a list of core IDs
+pub fn parse_cores_list(cores_spec: &str) -> Result<Vec<u32>> {
+    let mut cores = Vec::new();
+
+    // Split by commas
+    for part in cores_spec.split(',') {
+        if part.contains('-') {
+            // Handle ranges like "0-3"
+            let range: Vec<&str> = part.split('-').collect();
+            if range.len() != 2 {
+                anyhow::bail!("Invalid core range specification: {}", part);
+            }
+
+            let start = range[0]
+                .parse::<u32>()
+                .with_context(|| format!("Invalid core number: {}", range[0]))?;
+            let end = range[1]
+                .parse::<u32>()
+                .with_context(|| format!("Invalid core number: {}", range[1]))?;
+
+            cores.extend(start..=end);
+        } else {
+            // Handle single core like "5"
+            let core = part
+                .parse::<u32>()
+                .with_context(|| format!("Invalid core number: {part}"))?;
+            cores.push(core);
+        }
+    }
+
+    Ok(cores)
+}
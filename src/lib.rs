@@ -1,4 +1,5 @@
 pub mod benchmarks;
+pub mod cgroup;
 pub mod command;
 pub mod config;
 pub mod cpu_binding;
@@ -85,6 +85,25 @@ pub fn copy_file(source: &Path, dest: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Total size in bytes of all files below a directory
+///
+/// Entries that disappear or cannot be read while walking are skipped, so this
+/// can be called on a directory that is being modified concurrently.
+pub fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return 0;
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| match entry.metadata() {
+            Ok(meta) if meta.is_dir() => dir_size(&entry.path()),
+            Ok(meta) => meta.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -222,6 +241,22 @@ mod tests {
         prepare_output_directory(&output_dir).unwrap();
     }
 
+    #[test]
+    fn test_dir_size() {
+        let tempdir = tempdir().unwrap();
+        let nested = tempdir.path().join("blocks").join("index");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(
+            tempdir.path().join("blocks").join("blk00000.dat"),
+            [0u8; 100],
+        )
+        .unwrap();
+        fs::write(nested.join("000001.ldb"), [0u8; 24]).unwrap();
+
+        assert_eq!(dir_size(&tempdir.path().join("blocks")), 124);
+        assert_eq!(dir_size(&tempdir.path().join("missing")), 0);
+    }
+
     #[test]
     fn test_copy_file() {
         let tempdir = tempdir().unwrap();